@@ -18,6 +18,8 @@ class AddPostCubit extends Cubit<AddPostState> {
 
     switch (res.type) {
       case Reply.StartedWatching:
+      // Adding a post that is watched already shows it as well
+      case Reply.AlreadyWatching:
         assert(res.data != null, 'Successful reply should include post id');
         final post = _store.posts[res.data];
         assert(post != null, 'Watched post should be in the map');
@@ -10,15 +10,27 @@ part 'post_state.dart';
 class PostCubit extends Cubit<PostState> {
   final _store = Store.instance;
   StreamSubscription<PostedReply>? scoreTickSub;
-  PostCubit(Post post) : super(PostActive(post)) {
+  PostCubit(Post post) : super(PostActive.fromPost(post)) {
     _subscribe();
   }
 
   void _subscribe() {
     assert(scoreTickSub == null, 'Should only subscribe to post ticks once');
-    scoreTickSub = rid.replyChannel.stream
-        .where((x) => x.type == Reply.UpdatedScores)
-        .listen((_) => _refreshState());
+    scoreTickSub = rid.replyChannel.stream.listen((reply) {
+      if (reply.type == Reply.UpdatedScores && _hasNewScore(reply.data)) {
+        _appendScores();
+      } else if (reply.type == Reply.CompactedScores) {
+        // Compacting changes the indexes of the scores, so all of them are reloaded
+        _refreshState();
+      }
+    });
+  }
+
+  // The reply data lists the posts that got a new score, i.e. `t3_abc=42,t3_def=7`
+  bool _hasNewScore(String? changes) {
+    final postId = state.postId;
+    return changes != null &&
+        changes.split(',').any((change) => change.split('=').first == postId);
   }
 
   Future<void> _unsubscribe() async {
@@ -26,6 +38,14 @@ class PostCubit extends Cubit<PostState> {
     scoreTickSub = null;
   }
 
+  void _appendScores() {
+    final postActive = state;
+    if (postActive is! PostActive) return;
+    final added =
+        _store.scoresAfter(postActive.postId, postActive.scores.length);
+    emit(postActive.withScores(added));
+  }
+
   Future<void> _refreshState() async {
     assert(state is PostActive, 'Can only refresh active posts');
     final postActive = state as PostActive;
@@ -35,7 +55,7 @@ class PostCubit extends Cubit<PostState> {
     if (post == null) {
       emit(postActive.intoRemoved());
     } else {
-      emit(PostActive(post));
+      emit(PostActive.fromPost(post));
     }
   }
 
@@ -11,8 +11,15 @@ abstract class PostState {
 @immutable
 class PostActive extends PostState {
   final Post post;
+  // Scores rendered so far, new ones are appended instead of reloading all of them
+  final List<Score> scores;
 
-  PostActive(this.post) : super(post.id, post.url);
+  PostActive(this.post, this.scores) : super(post.id, post.url);
+
+  PostActive.fromPost(Post post) : this(post, post.scores);
+
+  PostActive withScores(List<Score> added) =>
+      PostActive(post, [...scores, ...added]);
 
   PostRemoved intoRemoved() => PostRemoved.fromPostActive(this);
 }
@@ -18,7 +18,10 @@ class PostsCubit extends Cubit<PostsState> {
   void _subscribe() {
     _postAddedOrRemovedSub = rid.replyChannel.stream
         .where((x) =>
-            x.type == Reply.StartedWatching || x.type == Reply.StoppedWatching)
+            x.type == Reply.StartedWatching ||
+            x.type == Reply.StoppedWatching ||
+            // Watch sources add and remove posts on their own
+            x.type == Reply.UpdatedSource)
         .listen((_) => _refresh());
   }
 
@@ -28,9 +31,8 @@ class PostsCubit extends Cubit<PostsState> {
   }
 
   void _refresh() {
-    final posts = _store.posts.values.toList();
     // Show most recently added post first
-    posts.sort((a, b) => a.scores.length.compareTo(b.scores.length));
+    final posts = _store.postsByAdded();
     emit(PostsState(posts));
   }
 
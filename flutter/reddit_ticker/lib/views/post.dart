@@ -20,7 +20,7 @@ class PostView extends StatelessWidget {
     return BlocBuilder<PostCubit, PostState>(builder: (context, state) {
       if (state is PostActive) {
         final post = state.post;
-        final chartData = _toChartData(post.scores);
+        final chartData = _toChartData(state.scores);
         final chart = charts.LineChart([chartData], animate: true);
         return Dismissible(
           key: Key("Post Dismissible ${state.post.id}"),
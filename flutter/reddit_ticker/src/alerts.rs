@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{anyhow, Result};
+
+use crate::reddit::Score;
+
+/// Separates the rule id from the post id in [Reply::AlertTriggered](crate::Reply)
+pub const ALERT_SEPARATOR: char = ':';
+
+// -----------------
+// Alert Kind
+// -----------------
+#[rid::model]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlertKind {
+    /// Score rose to or above the threshold
+    Crosses,
+    /// Score rose by at least the threshold within the window
+    Gains,
+    /// Score dropped by at least the threshold within the window
+    Drops,
+}
+
+impl AlertKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::Crosses => "crosses",
+            AlertKind::Gains => "gains",
+            AlertKind::Drops => "drops",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "crosses" => Some(AlertKind::Crosses),
+            "gains" => Some(AlertKind::Gains),
+            "drops" => Some(AlertKind::Drops),
+            _ => None,
+        }
+    }
+}
+
+// -----------------
+// Alert Rule
+// -----------------
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlertRule {
+    pub id: u32,
+    /// Post the rule applies to, all posts if `None`
+    pub post_id: Option<String>,
+    pub kind: AlertKind,
+    pub threshold: i32,
+    /// Secs of score history considered by [AlertKind::Gains] and [AlertKind::Drops]
+    pub window_secs: u64,
+}
+
+impl AlertRule {
+    pub fn new(
+        id: u32,
+        post_id: Option<String>,
+        kind: AlertKind,
+        threshold: i32,
+        window_secs: u64,
+    ) -> Result<Self> {
+        match kind {
+            AlertKind::Crosses => {}
+            AlertKind::Gains | AlertKind::Drops => {
+                if threshold <= 0 {
+                    return Err(anyhow!(
+                        "Score change needs to be positive, got {}",
+                        threshold
+                    ));
+                }
+                if window_secs == 0 {
+                    return Err(anyhow!("Window of a score change needs to be at least 1s"));
+                }
+            }
+        }
+        Ok(Self {
+            id,
+            post_id,
+            kind,
+            threshold,
+            window_secs,
+        })
+    }
+
+    fn applies_to(&self, post_id: &str) -> bool {
+        match &self.post_id {
+            Some(id) => id == post_id,
+            None => true,
+        }
+    }
+
+    /// Returns `true` if the condition of the rule holds for the post with the given `scores`
+    /// ordered by the time they were taken.
+    fn holds(&self, scores: &[Score]) -> bool {
+        let current = match scores.last() {
+            Some(current) => current,
+            None => return false,
+        };
+        match self.kind {
+            AlertKind::Crosses => current.score >= self.threshold,
+            AlertKind::Gains | AlertKind::Drops => {
+                let window_start = current
+                    .secs_since_post_added
+                    .saturating_sub(self.window_secs);
+                let window = scores
+                    .iter()
+                    .rev()
+                    .take_while(|score| score.secs_since_post_added >= window_start);
+                if self.kind == AlertKind::Gains {
+                    let min = window
+                        .map(|score| score.min_score)
+                        .min()
+                        .unwrap_or(current.score);
+                    current.score - min >= self.threshold
+                } else {
+                    let max = window
+                        .map(|score| score.max_score)
+                        .max()
+                        .unwrap_or(current.score);
+                    max - current.score >= self.threshold
+                }
+            }
+        }
+    }
+}
+
+// -----------------
+// Alert Engine
+// -----------------
+/// Evaluates the alert rules against the scores of posts.
+///
+/// A rule fires once when its condition starts to hold for a post and fires again only after
+/// the condition stopped holding in between.
+#[derive(Debug, Default)]
+pub struct AlertEngine {
+    rules: BTreeMap<u32, AlertRule>,
+    /// Highest id any rule ever had so that ids of removed rules aren't used again
+    last_rule_id: u32,
+    /// Rule and post ids for which the rule's condition held when last evaluated
+    active: HashSet<(u32, String)>,
+}
+
+impl AlertEngine {
+    pub fn next_rule_id(&self) -> u32 {
+        self.last_rule_id + 1
+    }
+
+    /// Restores the highest id any rule had, including rules that were removed since.
+    pub fn set_last_rule_id(&mut self, id: u32) {
+        self.last_rule_id = self.last_rule_id.max(id);
+    }
+
+    pub fn add_rule(&mut self, rule: AlertRule) {
+        self.set_last_rule_id(rule.id);
+        self.rules.insert(rule.id, rule);
+    }
+
+    pub fn remove_rule(&mut self, id: u32) -> Option<AlertRule> {
+        self.active.retain(|(rule_id, _)| *rule_id != id);
+        self.rules.remove(&id)
+    }
+
+    /// Removes all state of the post including rules that only apply to it.
+    pub fn remove_post(&mut self, post_id: &str) {
+        self.active.retain(|(_, id)| id != post_id);
+        self.rules
+            .retain(|_, rule| rule.post_id.as_deref() != Some(post_id));
+    }
+
+    /// Returns the ids of the rules that fired for the post after a score was added.
+    pub fn evaluate(&mut self, post_id: &str, scores: &[Score]) -> Vec<u32> {
+        let mut fired = vec![];
+        for rule in self.rules.values().filter(|rule| rule.applies_to(post_id)) {
+            let key = (rule.id, post_id.to_string());
+            if rule.holds(scores) {
+                // Posts that already crossed the score before we watched them don't fire
+                let was_below = rule.kind != AlertKind::Crosses
+                    || (scores.len() >= 2 && scores[scores.len() - 2].score < rule.threshold);
+                if self.active.insert(key) && was_below {
+                    fired.push(rule.id);
+                }
+            } else {
+                self.active.remove(&key);
+            }
+        }
+        fired
+    }
+}
+
+/// Encodes the payload of [Reply::AlertTriggered](crate::Reply), i.e. `3:t3_abc123`.
+pub fn alert_payload(rule_id: u32, post_id: &str) -> String {
+    format!("{}{}{}", rule_id, ALERT_SEPARATOR, post_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POST_ID: &str = "t3_abc";
+
+    fn score(secs_since_post_added: u64, score: i32) -> Score {
+        bucket(secs_since_post_added, score, score, score)
+    }
+
+    /// Compacted score that went as low as `min_score` and as high as `max_score`.
+    fn bucket(secs_since_post_added: u64, score: i32, min_score: i32, max_score: i32) -> Score {
+        Score {
+            secs_since_post_added,
+            score,
+            min_score,
+            max_score,
+            num_comments: 0,
+            upvote_ratio: 1.0,
+            front_page_rank: 1,
+            resolution_secs: 0,
+        }
+    }
+
+    fn engine_with_rule(kind: AlertKind, threshold: i32, window_secs: u64) -> AlertEngine {
+        let mut engine = AlertEngine::default();
+        engine.add_rule(AlertRule::new(1, None, kind, threshold, window_secs).unwrap());
+        engine
+    }
+
+    /// Evaluates the rules each time a score is added and returns the index of the score and
+    /// the id for each rule that fired.
+    fn evaluate_each(engine: &mut AlertEngine, scores: &[Score]) -> Vec<(usize, u32)> {
+        (1..=scores.len())
+            .flat_map(|len| {
+                let fired = engine.evaluate(POST_ID, &scores[..len]);
+                fired.into_iter().map(move |id| (len - 1, id))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crosses_fires_when_score_rises_to_threshold() {
+        let mut engine = engine_with_rule(AlertKind::Crosses, 100, 0);
+        let scores = vec![
+            score(0, 90),
+            score(10, 100),
+            score(20, 120),
+            score(30, 80),
+            score(40, 101),
+        ];
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![(1, 1), (4, 1)]);
+    }
+
+    #[test]
+    fn crosses_ignores_posts_above_threshold_when_first_watched() {
+        let mut engine = engine_with_rule(AlertKind::Crosses, 100, 0);
+        let scores = vec![score(0, 150), score(10, 160)];
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![]);
+    }
+
+    #[test]
+    fn gains_only_count_within_window() {
+        let mut engine = engine_with_rule(AlertKind::Gains, 50, 60);
+        let scores = vec![score(0, 10), score(30, 40), score(60, 61)];
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![(2, 1)]);
+
+        let mut engine = engine_with_rule(AlertKind::Gains, 50, 60);
+        let scores = vec![score(0, 10), score(100, 40), score(130, 61)];
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![]);
+    }
+
+    #[test]
+    fn drops_consider_max_score_of_buckets() {
+        let mut engine = engine_with_rule(AlertKind::Drops, 50, 60);
+        let scores = vec![bucket(0, 160, 140, 200), score(60, 140), score(90, 130)];
+        // Fires once while the drop holds
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![(1, 1)]);
+        assert!(!engine
+            .rules
+            .get(&1)
+            .unwrap()
+            .holds(&[score(0, 160), score(60, 140)]));
+    }
+
+    #[test]
+    fn rules_only_apply_to_their_post() {
+        let mut engine = AlertEngine::default();
+        let rule = AlertRule::new(1, Some("t3_other".to_string()), AlertKind::Crosses, 100, 0);
+        engine.add_rule(rule.unwrap());
+        let scores = vec![score(0, 90), score(10, 100)];
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![]);
+        assert_eq!(engine.evaluate("t3_other", &scores), vec![1]);
+
+        engine.remove_post("t3_other");
+        assert!(engine.rules.is_empty());
+    }
+
+    #[test]
+    fn removed_rules_fire_again_once_added_back() {
+        let mut engine = engine_with_rule(AlertKind::Crosses, 100, 0);
+        let scores = vec![score(0, 90), score(10, 100)];
+        assert_eq!(evaluate_each(&mut engine, &scores), vec![(1, 1)]);
+
+        let rule = engine.remove_rule(1).unwrap();
+        engine.add_rule(rule);
+        assert_eq!(engine.evaluate(POST_ID, &scores), vec![1]);
+    }
+
+    #[test]
+    fn does_not_reuse_ids_of_removed_rules() {
+        let mut engine = AlertEngine::default();
+        assert_eq!(engine.next_rule_id(), 1);
+        for id in 1..=3 {
+            engine.add_rule(AlertRule::new(id, None, AlertKind::Crosses, 100, 0).unwrap());
+        }
+        engine.remove_rule(3);
+        assert_eq!(engine.next_rule_id(), 4);
+
+        engine.set_last_rule_id(7);
+        engine.set_last_rule_id(5);
+        assert_eq!(engine.next_rule_id(), 8);
+    }
+
+    #[test]
+    fn rejects_invalid_score_changes() {
+        assert!(AlertRule::new(1, None, AlertKind::Gains, 0, 60).is_err());
+        assert!(AlertRule::new(1, None, AlertKind::Drops, 10, 0).is_err());
+        assert!(AlertRule::new(1, None, AlertKind::Crosses, -10, 0).is_ok());
+    }
+}
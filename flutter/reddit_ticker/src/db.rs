@@ -1,14 +1,158 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{anyhow, Result};
-use rusqlite::{params, Connection, Row, NO_PARAMS};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row, Statement, NO_PARAMS};
 
-use crate::reddit::{Post, Score};
+use crate::{
+    alerts::{AlertKind, AlertRule},
+    reddit::{Post, Score},
+    retention::{RetentionPolicy, HOUR_SECS, MINUTE_SECS},
+    sources::{ListingSort, SourceKind, WatchSource},
+};
 
 pub const DB_NAME: &str = "reddit_ticker.sqlite";
 
+/// Limits which scores of each post are loaded.
+#[derive(Debug, Clone, Copy)]
+pub enum ScoresFilter {
+    All,
+    /// The given number of most recent scores
+    Latest(u32),
+    /// Scores added at or after the given time
+    Since(SystemTime),
+}
+
+/// Selects the [ScoresFilter] via [Msg::SetScoresFilter](crate::Msg).
+#[rid::model]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoresFilterKind {
+    All,
+    Latest,
+    /// Scores of the last secs
+    Since,
+}
+
+impl ScoresFilter {
+    /// Builds the filter of the given `kind` with the number of scores or secs in `value`.
+    pub fn new(kind: ScoresFilterKind, value: u64, now: SystemTime) -> Result<Self> {
+        match kind {
+            ScoresFilterKind::All => Ok(ScoresFilter::All),
+            ScoresFilterKind::Latest if value == 0 || value > u32::MAX as u64 => Err(anyhow!(
+                "Number of scores to load needs to be between 1 and {}, got {}",
+                u32::MAX,
+                value
+            )),
+            ScoresFilterKind::Latest => Ok(ScoresFilter::Latest(value as u32)),
+            ScoresFilterKind::Since => now
+                .checked_sub(Duration::from_secs(value))
+                .map(ScoresFilter::Since)
+                .ok_or_else(|| anyhow!("Window of {}s reaches too far back", value)),
+        }
+    }
+}
+
+// Each migration upgrades the schema by one version which is tracked via `PRAGMA user_version`.
+// The tables created by [DB::init_tables] are version 0, migrations are never edited once
+// released, instead a new one is appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: post metrics besides the score
+    "
+ALTER TABLE reddit_scores ADD COLUMN num_comments INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE reddit_scores ADD COLUMN upvote_ratio REAL NOT NULL DEFAULT 0;
+ALTER TABLE reddit_scores ADD COLUMN front_page_rank INTEGER NOT NULL DEFAULT 0;
+",
+    // 2: timestamps in milliseconds instead of seconds
+    "
+UPDATE reddit_posts SET added = added * 1000;
+UPDATE reddit_scores SET added = added * 1000;
+",
+    // 3: load scores of each post in order without sorting them
+    "
+CREATE INDEX IF NOT EXISTS idx_post_id_added ON reddit_scores (post_id, added);
+",
+    // 4: scores compacted into buckets by the retention policy
+    "
+ALTER TABLE reddit_scores ADD COLUMN min_score INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE reddit_scores ADD COLUMN max_score INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE reddit_scores ADD COLUMN resolution_secs INTEGER NOT NULL DEFAULT 0;
+UPDATE reddit_scores SET min_score = score, max_score = score;
+",
+    // 5: subreddits and users whose posts are watched automatically
+    "
+CREATE TABLE IF NOT EXISTS watch_sources (
+    source_key  TEXT PRIMARY KEY,
+    kind        TEXT NOT NULL,
+    name        TEXT NOT NULL,
+    sort        TEXT NOT NULL,
+    post_limit  INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS watch_source_posts (
+    source_key  TEXT NOT NULL,
+    post_id     TEXT NOT NULL,
+    PRIMARY KEY (source_key, post_id)
+);
+",
+    // 6: alert rules, rules without a post_id apply to all posts
+    "
+CREATE TABLE IF NOT EXISTS alert_rules (
+    rule_id      INTEGER PRIMARY KEY,
+    post_id      TEXT,
+    kind         TEXT NOT NULL,
+    threshold    INTEGER NOT NULL,
+    window_secs  INTEGER NOT NULL
+);
+",
+    // 7: settings that outlive a restart, only one row is ever stored
+    "
+CREATE TABLE IF NOT EXISTS settings (
+    id                            INTEGER PRIMARY KEY,
+    raw_scores_retention_secs     INTEGER NOT NULL,
+    minute_scores_retention_secs  INTEGER NOT NULL,
+    retention_interval_secs       INTEGER NOT NULL
+);
+",
+    // 8: posts of watch sources the user stopped watching
+    "
+CREATE TABLE IF NOT EXISTS watch_source_exclusions (
+    source_key  TEXT NOT NULL,
+    post_id     TEXT NOT NULL,
+    PRIMARY KEY (source_key, post_id)
+);
+",
+    // 9: scores are looked up via idx_post_id_added which covers the post id as well
+    "
+DROP INDEX IF EXISTS idx_post_id;
+",
+    // 10: source keys are case insensitive like subreddit and user names
+    "
+UPDATE OR REPLACE watch_sources SET source_key = lower(source_key);
+UPDATE OR REPLACE watch_source_posts SET source_key = lower(source_key);
+UPDATE OR REPLACE watch_source_exclusions SET source_key = lower(source_key);
+",
+    // 11: ids of removed alert rules aren't used again, sqlite_sequence tracks the highest one
+    "
+CREATE TABLE alert_rules_v11 (
+    rule_id      INTEGER PRIMARY KEY AUTOINCREMENT,
+    post_id      TEXT,
+    kind         TEXT NOT NULL,
+    threshold    INTEGER NOT NULL,
+    window_secs  INTEGER NOT NULL
+);
+INSERT INTO alert_rules_v11 (rule_id, post_id, kind, threshold, window_secs)
+SELECT rule_id, post_id, kind, threshold, window_secs FROM alert_rules;
+DROP TABLE alert_rules;
+ALTER TABLE alert_rules_v11 RENAME TO alert_rules;
+",
+];
+const SETTINGS_ROW_ID: u32 = 0;
+const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
 pub struct DB {
-    conn: Connection,
+    conn: Mutex<Connection>,
 }
 
 impl DB {
@@ -16,14 +160,18 @@ impl DB {
         let conn = Connection::open(path)
             .map_err(|err| anyhow!("Failed to open Database at: {}\nError: {}", path, err))?;
 
-        let db = Self { conn };
+        let db = Self {
+            conn: Mutex::new(conn),
+        };
+        db.check_schema_version()?;
         db.init_tables()?;
+        db.migrate()?;
 
         Ok(db)
     }
 
     fn init_tables(&self) -> Result<()> {
-        self.conn
+        self.conn()
             .execute_batch(
                 "
 BEGIN;
@@ -38,20 +186,91 @@ CREATE TABLE IF NOT EXISTS reddit_scores (
     added     INTEGER,
     score     INTEGER
 );
-CREATE INDEX IF NOT EXISTS idx_post_id ON reddit_scores (post_id);
 COMMIT;
 ",
             )
             .map_err(|err| anyhow!("Failed to create Database tables:\nError: {}", err))
     }
 
+    /// Databases that were migrated by a newer version of the app are refused since we cannot
+    /// know how their schema changed.
+    fn check_schema_version(&self) -> Result<()> {
+        let version = self.schema_version()?;
+        if version > SCHEMA_VERSION {
+            return Err(anyhow!(
+                "Database schema version {} is newer than the supported version {}",
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies all migrations the Database hasn't seen yet, each in its own transaction.
+    fn migrate(&self) -> Result<()> {
+        let mut version = self.schema_version()?;
+
+        // Builds tracking post metrics before the schema was versioned added the columns of the
+        // first migration without stamping the version
+        if version == 0 && self.has_column("reddit_scores", "num_comments")? {
+            self.conn()
+                .pragma_update(None, "user_version", &1)
+                .map_err(|err| anyhow!("Failed to stamp Database version:\nError: {}", err))?;
+            version = 1;
+        }
+
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let next_version = (idx + 1) as u32;
+            let conn = self.conn();
+            let tx = conn.unchecked_transaction()?;
+            tx.execute_batch(migration)
+                .and_then(|_| tx.pragma_update(None, "user_version", &next_version))
+                .and_then(|_| tx.commit())
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to migrate Database to version {}:\nError: {}",
+                        next_version,
+                        err
+                    )
+                })?;
+            rid::log_info!("Migrated Database to version {}", next_version);
+        }
+        Ok(())
+    }
+
+    /// The Database is shared by the threads polling, listing and compacting scores which use it
+    /// without holding a lock on the Store. Holding on to the connection for the duration of a
+    /// transaction keeps their transactions from interleaving.
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn schema_version(&self) -> Result<u32> {
+        self.conn()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(|err| anyhow!("Failed to read Database schema version:\nError: {}", err))
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> Result<bool> {
+        let count: i64 = self.conn().query_row(
+            "
+SELECT COUNT(*)
+FROM pragma_table_info(?1)
+WHERE name = ?2;
+",
+            params!(table, column),
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
     // -----------------
     // Insert Posts and Scores
     // -----------------
 
     pub fn insert_post(&self, post: &Post) -> Result<usize> {
-        let added: u32 = time_stamp_to_secs(post.added);
-        self.conn
+        let added = time_stamp_to_millis(post.added);
+        self.conn()
             .execute(
                 "
 INSERT OR IGNORE INTO reddit_posts (post_id, title, url, added)
@@ -62,83 +281,239 @@ VALUES (?1, ?2, ?3, ?4);
             .map_err(|err| anyhow!("Failed to add post with id {}:\nError: {}", post.id, err))
     }
 
-    pub fn insert_score(&self, post_id: &str, time_stamp: SystemTime, score: i32) -> Result<usize> {
-        let added = time_stamp_to_secs(time_stamp);
-        let res = self
-            .conn
-            .execute(
-                "
-INSERT OR IGNORE INTO reddit_scores (post_id, added, score)
-VALUES (?1, ?2, ?3);
-",
-                params!(post_id, added, score),
-            )
-            .map_err(|err| {
-                anyhow!(
-                    "Failed to insert score for post {}:\nError: {}",
-                    post_id,
-                    err
-                )
-            })?;
-
-        Ok(res)
+    /// Inserts all `scores` taken at `time_stamp` in one transaction.
+    /// Scores of posts that aren't in the Database (anymore) are skipped.
+    pub fn insert_scores(
+        &self,
+        time_stamp: SystemTime,
+        scores: &[(String, Score)],
+    ) -> Result<usize> {
+        let added = time_stamp_to_millis(time_stamp);
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let mut rows = 0;
+        {
+            let mut stmt = tx.prepare(INSERT_SCORE)?;
+            for (post_id, score) in scores {
+                rows += execute_insert_score(&mut stmt, post_id, added, score)?;
+            }
+        }
+        tx.commit()
+            .map_err(|err| anyhow!("Failed to commit scores:\nError: {}", err))?;
+        Ok(rows)
     }
+
     // -----------------
     // Retrieving Posts and Scores
     // -----------------
-    pub fn get_scores(&self, post: &Post) -> Result<Vec<Score>> {
-        let mut stmt = self.conn.prepare(
-            "
-SELECT added, score
-FROM reddit_scores
-WHERE post_id = (?1)
+    /// Loads all posts including their scores ordered by the time they were added.
+    ///
+    /// The scores of all posts are retrieved via a single query ordered by post and time which
+    /// is served by `idx_post_id_added` without sorting and allows us to group the scores while
+    /// iterating the rows.
+    pub fn get_all_posts(&self, filter: ScoresFilter) -> Result<Vec<Post>> {
+        let conn = self.conn();
+        let mut posts: Vec<Post> = {
+            let mut stmt = conn.prepare(
+                "
+SELECT post_id, title, url, added
+FROM reddit_posts
+ORDER BY added, post_id;
 ",
-        )?;
-
-        let results: Vec<_> = stmt
-            .query_map(params!(post.id), |row| try_extract_score(row, post.added))?
-            .filter_map(|x| match x {
-                Ok(score) => Some(score),
+            )?;
+            let rows = stmt.query_map(NO_PARAMS, try_extract_post)?;
+            rows.filter_map(|res| match res {
+                Ok(post) => Some(post),
                 Err(err) => {
-                    rid::log_warn!("Found invalid score in Database {}", err.to_string());
+                    rid::error!("A post couldn't be properly extracted", err.to_string());
                     None
                 }
             })
+            .collect()
+        };
+        let post_idxs: HashMap<String, usize> = posts
+            .iter()
+            .enumerate()
+            .map(|(idx, post)| (post.id.clone(), idx))
             .collect();
-        Ok(results)
-    }
 
-    pub fn get_all_posts(&self) -> Result<Vec<Post>> {
-        let mut stmt = self.conn.prepare(
+        let scores = match filter {
+            ScoresFilter::All => "reddit_scores",
+            ScoresFilter::Since(_) => "(SELECT * FROM reddit_scores WHERE added >= ?1)",
+            ScoresFilter::Latest(_) => {
+                "
+(SELECT *
+ FROM (SELECT *, ROW_NUMBER() OVER (PARTITION BY post_id ORDER BY added DESC) AS nth
+       FROM reddit_scores)
+ WHERE nth <= ?1)"
+            }
+        };
+        let mut stmt = conn.prepare(&format!(
             "
-SELECT post_id, title, url, added 
-FROM reddit_posts;
+SELECT {}
+FROM {} s
+JOIN reddit_posts p ON p.post_id = s.post_id
+ORDER BY s.post_id, s.added;
 ",
-        )?;
-        let results = stmt.query_map(NO_PARAMS, try_extract_post)?;
-        let mut posts: Vec<_> = results
-            .filter_map(|res| match res {
-                Ok(post) => Some(post),
+            POST_SCORE_COLUMNS, scores
+        ))?;
+        let mut rows = match filter {
+            ScoresFilter::All => stmt.query(NO_PARAMS)?,
+            ScoresFilter::Since(time_stamp) => {
+                stmt.query(params!(time_stamp_to_millis(time_stamp)))?
+            }
+            ScoresFilter::Latest(n) => stmt.query(params!(n))?,
+        };
+
+        // Scores of a post are consecutive, so we only look up the post when the id changes
+        let mut current_post_id = String::new();
+        let mut current_idx: Option<usize> = None;
+        while let Some(row) = rows.next()? {
+            let post_id: String = row.get(0)?;
+            if post_id != current_post_id {
+                current_idx = post_idxs.get(&post_id).copied();
+                current_post_id = post_id;
+            }
+            // Scores of posts that couldn't be extracted are skipped
+            let post = match current_idx {
+                Some(idx) => &mut posts[idx],
+                None => continue,
+            };
+            match try_extract_score(row, post.added) {
+                Ok(score) => post.scores.push(score),
                 Err(err) => {
-                    rid::error!("A post couldn't be properly extracted", err.to_string());
-                    None
+                    rid::log_warn!("Found invalid score in Database {}", err.to_string());
                 }
-            })
-            .collect();
-
-        for mut post in posts.iter_mut() {
-            let scores = self.get_scores(&post)?;
-            post.scores = scores
+            }
         }
         Ok(posts)
     }
 
+    // -----------------
+    // Compacting Scores
+    // -----------------
+    /// Compacts the scores of all posts according to the `policy` and returns the number of
+    /// score rows that were removed.
+    ///
+    /// Each post is compacted in its own transaction so that a failing post doesn't affect the
+    /// others.
+    pub fn downsample_scores(&self, policy: &RetentionPolicy, now: SystemTime) -> Result<usize> {
+        let posts: Vec<(String, i64)> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare("SELECT post_id, added FROM reddit_posts;")?;
+            let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut removed = 0;
+        for (post_id, added) in posts {
+            let secs_since_post_added = now
+                .duration_since(millis_to_time_stamp(added))
+                .unwrap_or_default()
+                .as_secs();
+            let minute_cutoff =
+                added + (policy.minute_cutoff_secs(secs_since_post_added) * 1000) as i64;
+            let hour_cutoff =
+                added + (policy.hour_cutoff_secs(secs_since_post_added) * 1000) as i64;
+            match self.downsample_post_scores(&post_id, minute_cutoff, hour_cutoff, |scores| {
+                policy.downsample(scores, secs_since_post_added)
+            }) {
+                Ok(rows) => removed += rows,
+                Err(err) => {
+                    rid::error!(
+                        format!("Failed to compact scores of post {}", post_id),
+                        err.to_string()
+                    );
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Replaces the scores of the post that still need compacting with the ones returned by
+    /// `downsample`. Those are the raw scores added before `minute_cutoff` millis and the ones
+    /// finer than an hour added before `hour_cutoff` millis. Buckets that were already
+    /// compacted are never touched.
+    fn downsample_post_scores<F>(
+        &self,
+        post_id: &str,
+        minute_cutoff: i64,
+        hour_cutoff: i64,
+        downsample: F,
+    ) -> Result<usize>
+    where
+        F: FnOnce(&mut Vec<Score>) -> bool,
+    {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let (post_added, mut scores) = {
+            let mut stmt = tx.prepare(&format!(
+                "
+SELECT {}
+FROM reddit_posts p
+JOIN reddit_scores s ON s.post_id = p.post_id
+WHERE p.post_id = ?1 AND ({})
+ORDER BY s.added;
+",
+                POST_SCORE_COLUMNS, NEEDS_COMPACTING
+            ))?;
+            let mut rows = stmt.query(params!(
+                post_id,
+                minute_cutoff,
+                MINUTE_SECS,
+                hour_cutoff,
+                HOUR_SECS
+            ))?;
+            let mut post_added = None;
+            let mut scores = vec![];
+            while let Some(row) = rows.next()? {
+                let added = millis_to_time_stamp(row.get(3)?);
+                post_added = Some(added);
+                scores.push(try_extract_score(row, added)?);
+            }
+            (post_added, scores)
+        };
+        let post_added = match post_added {
+            Some(post_added) => time_stamp_to_millis(post_added),
+            None => return Ok(0),
+        };
+
+        let len = scores.len();
+        if !downsample(&mut scores) {
+            return Ok(0);
+        }
+
+        tx.execute(
+            &format!(
+                "
+DELETE FROM reddit_scores AS s
+WHERE s.post_id = ?1 AND ({});
+",
+                NEEDS_COMPACTING
+            ),
+            params!(post_id, minute_cutoff, MINUTE_SECS, hour_cutoff, HOUR_SECS),
+        )
+        .map_err(|err| anyhow!("Failed to remove compacted scores:\nError: {}", err))?;
+        {
+            let mut stmt = tx.prepare(INSERT_SCORE)?;
+            for score in &scores {
+                let added = post_added + (score.secs_since_post_added * 1000) as i64;
+                execute_insert_score(&mut stmt, post_id, added, score)?;
+            }
+        }
+        tx.commit()
+            .map_err(|err| anyhow!("Failed to commit compacted scores:\nError: {}", err))?;
+        Ok(len.saturating_sub(scores.len()))
+    }
+
     // -----------------
     // Deleting Posts and Scores
     // -----------------
+    /// Removes the post and all its scores or nothing at all.
     pub fn delete_post(&self, post_id: &str) -> Result<usize> {
-        let post_rows_removed = self
-            .conn
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let post_rows_removed = tx
             .execute(
                 "
 DELETE FROM reddit_posts 
@@ -148,8 +523,7 @@ WHERE post_id = (?1);
             )
             .map_err(|err| anyhow!("Failed to remove post from table:\nError: {}", err))?;
 
-        let score_rows_removed = self
-            .conn
+        let score_rows_removed = tx
             .execute(
                 "
 DELETE FROM reddit_scores 
@@ -158,28 +532,423 @@ WHERE post_id = (?1);
                 params!(post_id),
             )
             .map_err(|err| anyhow!("Failed to remove scores from table:\nError: {}", err))?;
+
+        tx.execute(
+            "
+DELETE FROM watch_source_posts
+WHERE post_id = (?1);
+",
+            params!(post_id),
+        )
+        .map_err(|err| anyhow!("Failed to remove post from its sources:\nError: {}", err))?;
+
+        tx.execute(
+            "
+DELETE FROM alert_rules
+WHERE post_id = (?1);
+",
+            params!(post_id),
+        )
+        .map_err(|err| anyhow!("Failed to remove alert rules of post:\nError: {}", err))?;
+
+        tx.commit()
+            .map_err(|err| anyhow!("Failed to remove post {}:\nError: {}", post_id, err))?;
         Ok(post_rows_removed + score_rows_removed)
     }
+
+    // -----------------
+    // Watch Sources
+    // -----------------
+    /// Adds the source or updates its sort and limit if it exists already.
+    pub fn insert_watch_source(&self, source: &WatchSource) -> Result<usize> {
+        self.conn()
+            .execute(
+                "
+INSERT OR REPLACE INTO watch_sources (source_key, kind, name, sort, post_limit)
+VALUES (?1, ?2, ?3, ?4, ?5);
+",
+                params![
+                    source.key(),
+                    source.kind.as_str(),
+                    source.name,
+                    source.sort.as_str(),
+                    source.limit
+                ],
+            )
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to add watch source {}:\nError: {}",
+                    source.key(),
+                    err
+                )
+            })
+    }
+
+    /// Removes the source, the posts it added are left untouched.
+    pub fn delete_watch_source(&self, source_key: &str) -> Result<usize> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let rows = tx
+            .execute(
+                "DELETE FROM watch_sources WHERE source_key = ?1;",
+                params!(source_key),
+            )
+            .and_then(|rows| {
+                tx.execute(
+                    "DELETE FROM watch_source_posts WHERE source_key = ?1;",
+                    params!(source_key),
+                )
+                .map(|post_rows| rows + post_rows)
+            })
+            .and_then(|rows| {
+                tx.execute(
+                    "DELETE FROM watch_source_exclusions WHERE source_key = ?1;",
+                    params!(source_key),
+                )
+                .map(|excluded_rows| rows + excluded_rows)
+            })
+            .map_err(|err| anyhow!("Failed to remove watch source:\nError: {}", err))?;
+        tx.commit().map_err(|err| {
+            anyhow!(
+                "Failed to remove watch source {}:\nError: {}",
+                source_key,
+                err
+            )
+        })?;
+        Ok(rows)
+    }
+
+    pub fn get_watch_sources(&self) -> Result<Vec<WatchSource>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT kind, name, sort, post_limit FROM watch_sources;")?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+
+        let mut sources = vec![];
+        while let Some(row) = rows.next()? {
+            match try_extract_watch_source(row) {
+                Ok(source) => sources.push(source),
+                Err(err) => {
+                    rid::log_warn!("Found invalid watch source in Database {}", err.to_string());
+                }
+            }
+        }
+        Ok(sources)
+    }
+
+    /// Returns the ids of the posts added by each source keyed by source.
+    pub fn get_watch_source_posts(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT source_key, post_id FROM watch_source_posts;")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn insert_watch_source_post(&self, source_key: &str, post_id: &str) -> Result<usize> {
+        self.conn()
+            .execute(
+                "
+INSERT OR IGNORE INTO watch_source_posts (source_key, post_id)
+VALUES (?1, ?2);
+",
+                params![source_key, post_id],
+            )
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to add post {} to watch source {}:\nError: {}",
+                    post_id,
+                    source_key,
+                    err
+                )
+            })
+    }
+
+    pub fn delete_watch_source_post(&self, source_key: &str, post_id: &str) -> Result<usize> {
+        self.conn()
+            .execute(
+                "DELETE FROM watch_source_posts WHERE source_key = ?1 AND post_id = ?2;",
+                params![source_key, post_id],
+            )
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to remove post {} from watch source {}:\nError: {}",
+                    post_id,
+                    source_key,
+                    err
+                )
+            })
+    }
+
+    /// Returns the ids of the posts the user stopped watching keyed by the source that listed
+    /// them.
+    pub fn get_watch_source_exclusions(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT source_key, post_id FROM watch_source_exclusions;")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn insert_watch_source_exclusion(&self, source_key: &str, post_id: &str) -> Result<usize> {
+        self.conn()
+            .execute(
+                "
+INSERT OR IGNORE INTO watch_source_exclusions (source_key, post_id)
+VALUES (?1, ?2);
+",
+                params![source_key, post_id],
+            )
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to exclude post {} from watch source {}:\nError: {}",
+                    post_id,
+                    source_key,
+                    err
+                )
+            })
+    }
+
+    pub fn delete_watch_source_exclusion(&self, source_key: &str, post_id: &str) -> Result<usize> {
+        self.conn()
+            .execute(
+                "DELETE FROM watch_source_exclusions WHERE source_key = ?1 AND post_id = ?2;",
+                params![source_key, post_id],
+            )
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to remove excluded post {} from watch source {}:\nError: {}",
+                    post_id,
+                    source_key,
+                    err
+                )
+            })
+    }
+
+    // -----------------
+    // Alert Rules
+    // -----------------
+    pub fn insert_alert_rule(&self, rule: &AlertRule) -> Result<usize> {
+        self.conn()
+            .execute(
+                "
+INSERT OR REPLACE INTO alert_rules (rule_id, post_id, kind, threshold, window_secs)
+VALUES (?1, ?2, ?3, ?4, ?5);
+",
+                params![
+                    rule.id,
+                    rule.post_id,
+                    rule.kind.as_str(),
+                    rule.threshold,
+                    rule.window_secs as i64
+                ],
+            )
+            .map_err(|err| anyhow!("Failed to add alert rule {}:\nError: {}", rule.id, err))
+    }
+
+    pub fn delete_alert_rule(&self, rule_id: u32) -> Result<usize> {
+        self.conn()
+            .execute(
+                "DELETE FROM alert_rules WHERE rule_id = ?1;",
+                params!(rule_id),
+            )
+            .map_err(|err| anyhow!("Failed to remove alert rule {}:\nError: {}", rule_id, err))
+    }
+
+    pub fn get_alert_rules(&self) -> Result<Vec<AlertRule>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT rule_id, post_id, kind, threshold, window_secs FROM alert_rules ORDER BY rule_id;",
+        )?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+
+        let mut rules = vec![];
+        while let Some(row) = rows.next()? {
+            match try_extract_alert_rule(row) {
+                Ok(rule) => rules.push(rule),
+                Err(err) => {
+                    rid::log_warn!("Found invalid alert rule in Database {}", err.to_string());
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Returns the highest id any alert rule had, including rules that were removed since.
+    pub fn get_last_alert_rule_id(&self) -> Result<u32> {
+        let id: Option<u32> = self
+            .conn()
+            .query_row(
+                "SELECT seq FROM sqlite_sequence WHERE name = 'alert_rules';",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|err| anyhow!("Failed to retrieve last alert rule id:\nError: {}", err))?;
+        Ok(id.unwrap_or(0))
+    }
+
+    // -----------------
+    // Settings
+    // -----------------
+    pub fn upsert_retention(&self, policy: &RetentionPolicy) -> Result<usize> {
+        self.conn()
+            .execute(
+                "
+INSERT OR REPLACE INTO settings
+  (id, raw_scores_retention_secs, minute_scores_retention_secs, retention_interval_secs)
+VALUES (?1, ?2, ?3, ?4);
+",
+                params![
+                    SETTINGS_ROW_ID,
+                    policy.raw_secs as i64,
+                    policy.minute_secs as i64,
+                    policy.interval_secs as i64
+                ],
+            )
+            .map_err(|err| anyhow!("Failed to store retention policy:\nError: {}", err))
+    }
+
+    /// Returns the retention policy unless it was never changed.
+    pub fn get_retention(&self) -> Result<Option<RetentionPolicy>> {
+        self.conn()
+            .query_row(
+                "
+SELECT raw_scores_retention_secs, minute_scores_retention_secs, retention_interval_secs
+FROM settings
+WHERE id = ?1;
+",
+                params!(SETTINGS_ROW_ID),
+                try_extract_retention,
+            )
+            .optional()
+            .map_err(|err| anyhow!("Failed to retrieve retention policy:\nError: {}", err))
+    }
 }
 
 // -----------------
 // Sqlite helpers
 // -----------------
+// Post columns followed by score columns as expected by [try_extract_post] and
+// [try_extract_score]
+const POST_SCORE_COLUMNS: &str = "
+p.post_id, p.title, p.url, p.added,
+s.added, s.score, s.num_comments, s.upvote_ratio, s.front_page_rank,
+s.min_score, s.max_score, s.resolution_secs";
+
+// Scores (aliased as `s`) that still need compacting, raw ones before the minute cutoff ?2
+// and ones finer than an hour before the hour cutoff ?4
+const NEEDS_COMPACTING: &str = "
+(s.resolution_secs < ?3 AND s.added < ?2) OR (s.resolution_secs < ?5 AND s.added < ?4)";
+
+// Scores of posts that were removed in the meantime are skipped
+const INSERT_SCORE: &str = "
+INSERT OR IGNORE INTO reddit_scores
+  (post_id, added, score, num_comments, upvote_ratio, front_page_rank,
+   min_score, max_score, resolution_secs)
+SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9
+WHERE EXISTS (SELECT 1 FROM reddit_posts WHERE post_id = ?1);
+";
+
+fn execute_insert_score(
+    stmt: &mut Statement,
+    post_id: &str,
+    added: i64,
+    score: &Score,
+) -> Result<usize> {
+    stmt.execute(params!(
+        post_id,
+        added,
+        score.score,
+        score.num_comments,
+        score.upvote_ratio,
+        score.front_page_rank,
+        score.min_score,
+        score.max_score,
+        score.resolution_secs
+    ))
+    .map_err(|err| {
+        anyhow!(
+            "Failed to insert score for post {}:\nError: {}",
+            post_id,
+            err
+        )
+    })
+}
+
+/// Extracts the score from the columns following the post columns of a row selecting
+/// [POST_SCORE_COLUMNS].
 fn try_extract_score(row: &Row, post_added: SystemTime) -> rusqlite::Result<Score> {
-    // Score timestamps are stored [UNIX_EPOCH] seconds, however the rest
+    // Score timestamps are stored [UNIX_EPOCH] milliseconds, however the rest
     // of the app treats score timestamps based on the time the post was added.
-    let secs: u32 = row.get(0)?;
-    let time_stamp = secs_to_time_stamp(secs);
+    let millis: i64 = row.get(4)?;
+    let time_stamp = millis_to_time_stamp(millis);
     let secs_since_post_added = time_stamp
         .duration_since(post_added)
-        .expect("Invalid timestamp")
+        .map_err(|_| {
+            rusqlite::Error::FromSqlConversionFailure(
+                4,
+                Type::Integer,
+                anyhow!("Score added at {} before its post was added", millis).into(),
+            )
+        })?
         .as_secs();
 
-    let score: i32 = row.get(1)?;
-
     Ok(Score {
         secs_since_post_added,
-        score,
+        score: row.get(5)?,
+        num_comments: row.get(6)?,
+        upvote_ratio: row.get(7)?,
+        front_page_rank: row.get(8)?,
+        min_score: row.get(9)?,
+        max_score: row.get(10)?,
+        resolution_secs: row.get(11)?,
+    })
+}
+
+fn try_extract_watch_source(row: &Row) -> rusqlite::Result<WatchSource> {
+    let kind: String = row.get(0)?;
+    let sort: String = row.get(2)?;
+    let invalid = |idx: usize, value: String| {
+        rusqlite::Error::FromSqlConversionFailure(
+            idx,
+            Type::Text,
+            anyhow!("Unknown value '{}'", value).into(),
+        )
+    };
+    Ok(WatchSource {
+        kind: SourceKind::parse(&kind).ok_or_else(|| invalid(0, kind.clone()))?,
+        name: row.get(1)?,
+        sort: ListingSort::parse(&sort).ok_or_else(|| invalid(2, sort.clone()))?,
+        limit: row.get(3)?,
+    })
+}
+
+fn try_extract_alert_rule(row: &Row) -> rusqlite::Result<AlertRule> {
+    let kind: String = row.get(2)?;
+    let kind = AlertKind::parse(&kind).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            2,
+            Type::Text,
+            anyhow!("Unknown alert kind '{}'", kind).into(),
+        )
+    })?;
+    let window_secs: i64 = row.get(4)?;
+    Ok(AlertRule {
+        id: row.get(0)?,
+        post_id: row.get(1)?,
+        kind,
+        threshold: row.get(3)?,
+        window_secs: window_secs.max(0) as u64,
+    })
+}
+
+fn try_extract_retention(row: &Row) -> rusqlite::Result<RetentionPolicy> {
+    let raw_secs: i64 = row.get(0)?;
+    let minute_secs: i64 = row.get(1)?;
+    let interval_secs: i64 = row.get(2)?;
+    Ok(RetentionPolicy {
+        raw_secs: raw_secs.max(0) as u64,
+        minute_secs: minute_secs.max(0) as u64,
+        // A zero interval would compact scores in a busy loop
+        interval_secs: interval_secs.max(1) as u64,
     })
 }
 
@@ -188,16 +957,455 @@ fn try_extract_post(row: &Row) -> rusqlite::Result<Post> {
         id: row.get(0)?,
         title: row.get(1)?,
         url: row.get(2)?,
-        added: secs_to_time_stamp(row.get(3)?),
+        added: millis_to_time_stamp(row.get(3)?),
         scores: vec![],
     })
 }
-fn time_stamp_to_secs(time_stamp: SystemTime) -> u32 {
-    // max u32:          4,294,967,295
-    // UNIX_EPOCH secs: ~1,631,804,843
-    time_stamp.duration_since(UNIX_EPOCH).unwrap().as_secs() as u32
+
+fn time_stamp_to_millis(time_stamp: SystemTime) -> i64 {
+    // A clock set before [UNIX_EPOCH] results in negative millis
+    match time_stamp.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+fn millis_to_time_stamp(millis: i64) -> SystemTime {
+    if millis >= 0 {
+        UNIX_EPOCH + Duration::from_millis(millis as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_millis(millis.unsigned_abs())
+    }
 }
 
-fn secs_to_time_stamp(secs: u32) -> SystemTime {
-    UNIX_EPOCH + Duration::from_secs(secs as u64)
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process,
+    };
+
+    use super::*;
+
+    // Schema of the tables as created before the Database was versioned
+    const SCHEMA_V0: &str = "
+CREATE TABLE reddit_posts (
+    post_id  TEXT PRIMARY KEY,
+    title    TEXT,
+    url      TEXT,
+    added    INTEGER
+);
+CREATE TABLE reddit_scores (
+    post_id   TEXT,
+    added     INTEGER,
+    score     INTEGER
+);
+CREATE INDEX idx_post_id ON reddit_scores (post_id);
+";
+
+    /// Path of a Database file that doesn't exist yet and is unique to the test.
+    fn db_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("reddit_ticker_{}_{}.sqlite", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn create_v0_db(path: &Path, extra_sql: &str) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(SCHEMA_V0).unwrap();
+        conn.execute_batch(extra_sql).unwrap();
+        conn.execute_batch(
+            "
+INSERT INTO reddit_posts (post_id, title, url, added)
+VALUES ('t3_abc', 'title', 'https://www.reddit.com/comments/abc', 1600000000);
+INSERT INTO reddit_scores (post_id, added, score) VALUES ('t3_abc', 1600000060, 42);
+",
+        )
+        .unwrap();
+    }
+
+    fn has_index(db: &DB, name: &str) -> bool {
+        let count: i64 = db
+            .conn()
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = ?1;",
+                params!(name),
+                |row| row.get(0),
+            )
+            .unwrap();
+        count > 0
+    }
+
+    fn assert_migrated(db: &DB) {
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        for column in &[
+            "num_comments",
+            "upvote_ratio",
+            "front_page_rank",
+            "min_score",
+            "max_score",
+            "resolution_secs",
+        ] {
+            assert!(
+                db.has_column("reddit_scores", column).unwrap(),
+                "column: {}",
+                column
+            );
+        }
+        assert!(db.has_column("watch_sources", "source_key").unwrap());
+        assert!(db.has_column("watch_source_exclusions", "post_id").unwrap());
+        assert!(db.has_column("alert_rules", "rule_id").unwrap());
+        assert!(db
+            .has_column("settings", "retention_interval_secs")
+            .unwrap());
+
+        assert!(!has_index(db, "idx_post_id"));
+        assert!(has_index(db, "idx_post_id_added"));
+
+        let posts = db.get_all_posts(ScoresFilter::All).unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(
+            posts[0].added,
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+        );
+        let score = &posts[0].scores[0];
+        assert_eq!(score.secs_since_post_added, 60);
+        assert_eq!(
+            (score.score, score.min_score, score.max_score),
+            (42, 42, 42)
+        );
+    }
+
+    #[test]
+    fn migrates_unversioned_db() {
+        let path = db_path("unversioned");
+        create_v0_db(&path, "");
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_migrated(&db);
+
+        // Opening it again doesn't apply any migrations twice
+        drop(db);
+        assert_migrated(&DB::new(path.to_str().unwrap()).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn migrates_unversioned_db_with_post_metrics() {
+        let path = db_path("metrics");
+        create_v0_db(
+            &path,
+            "
+ALTER TABLE reddit_scores ADD COLUMN num_comments INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE reddit_scores ADD COLUMN upvote_ratio REAL NOT NULL DEFAULT 0;
+ALTER TABLE reddit_scores ADD COLUMN front_page_rank INTEGER NOT NULL DEFAULT 0;
+",
+        );
+
+        assert_migrated(&DB::new(path.to_str().unwrap()).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn stores_retention_policy() {
+        let path = db_path("retention");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert!(db.get_retention().unwrap().is_none());
+
+        let policy = RetentionPolicy {
+            raw_secs: 120,
+            minute_secs: 7200,
+            interval_secs: 30,
+        };
+        db.upsert_retention(&policy).unwrap();
+        db.upsert_retention(&policy).unwrap();
+        let stored = db.get_retention().unwrap().unwrap();
+        assert_eq!(
+            (stored.raw_secs, stored.minute_secs, stored.interval_secs),
+            (120, 7200, 30)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn loads_posts_in_added_order_with_their_scores() {
+        let path = db_path("load_order");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        let epoch = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        // Ids are ordered the other way around than the time the posts were added
+        for (id, added_secs) in &[("t3_c", 0), ("t3_b", 10), ("t3_a", 20), ("t3_empty", 30)] {
+            let post = Post {
+                added: epoch + Duration::from_secs(*added_secs),
+                id: id.to_string(),
+                title: id.to_string(),
+                url: format!("https://www.reddit.com/comments/{}", id),
+                scores: vec![],
+            };
+            db.insert_post(&post).unwrap();
+        }
+        let score = |score: i32| Score {
+            secs_since_post_added: 0,
+            score,
+            min_score: score,
+            max_score: score,
+            num_comments: 0,
+            upvote_ratio: 1.0,
+            front_page_rank: 0,
+            resolution_secs: 0,
+        };
+        for secs in &[40, 50, 60] {
+            let scores = vec![
+                ("t3_a".to_string(), score(*secs)),
+                ("t3_b".to_string(), score(*secs + 1)),
+                ("t3_c".to_string(), score(*secs + 2)),
+            ];
+            db.insert_scores(epoch + Duration::from_secs(*secs as u64), &scores)
+                .unwrap();
+        }
+
+        let posts = db.get_all_posts(ScoresFilter::All).unwrap();
+        let ids: Vec<&str> = posts.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["t3_c", "t3_b", "t3_a", "t3_empty"]);
+        let scores: Vec<Vec<i32>> = posts
+            .iter()
+            .map(|post| post.scores.iter().map(|x| x.score).collect())
+            .collect();
+        assert_eq!(
+            scores,
+            vec![vec![42, 52, 62], vec![41, 51, 61], vec![40, 50, 60], vec![]]
+        );
+        assert_eq!(posts[1].scores[0].secs_since_post_added, 30);
+
+        let posts = db.get_all_posts(ScoresFilter::Latest(2)).unwrap();
+        let scores: Vec<Vec<i32>> = posts
+            .iter()
+            .map(|post| post.scores.iter().map(|x| x.score).collect())
+            .collect();
+        assert_eq!(
+            scores,
+            vec![vec![52, 62], vec![51, 61], vec![50, 60], vec![]]
+        );
+
+        let posts = db
+            .get_all_posts(ScoresFilter::Since(epoch + Duration::from_secs(60)))
+            .unwrap();
+        let scores: Vec<Vec<i32>> = posts
+            .iter()
+            .map(|post| post.scores.iter().map(|x| x.score).collect())
+            .collect();
+        assert_eq!(scores, vec![vec![62], vec![61], vec![60], vec![]]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skips_scores_of_removed_posts() {
+        let path = db_path("removed_posts");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        let added = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let post = Post {
+            added,
+            id: "t3_abc".to_string(),
+            title: "title".to_string(),
+            url: "https://www.reddit.com/comments/abc".to_string(),
+            scores: vec![],
+        };
+        db.insert_post(&post).unwrap();
+
+        let score = Score {
+            secs_since_post_added: 60,
+            score: 42,
+            min_score: 42,
+            max_score: 42,
+            num_comments: 0,
+            upvote_ratio: 1.0,
+            front_page_rank: 0,
+            resolution_secs: 0,
+        };
+        let scores = vec![
+            ("t3_abc".to_string(), score.clone()),
+            ("t3_removed".to_string(), score),
+        ];
+        let rows = db
+            .insert_scores(added + Duration::from_secs(60), &scores)
+            .unwrap();
+        assert_eq!(rows, 1);
+
+        let posts = db.get_all_posts(ScoresFilter::All).unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].scores.len(), 1);
+        let orphaned: i64 = db
+            .conn()
+            .query_row(
+                "SELECT COUNT(*) FROM reddit_scores WHERE post_id = 't3_removed';",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(orphaned, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn does_not_rewrite_compacted_buckets() {
+        let path = db_path("compacted_buckets");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        let added = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let post = Post {
+            added,
+            id: "t3_abc".to_string(),
+            title: "title".to_string(),
+            url: "https://www.reddit.com/comments/abc".to_string(),
+            scores: vec![],
+        };
+        db.insert_post(&post).unwrap();
+        for secs in (0..240).step_by(30) {
+            let score = Score {
+                secs_since_post_added: secs,
+                score: secs as i32,
+                min_score: secs as i32,
+                max_score: secs as i32,
+                num_comments: 0,
+                upvote_ratio: 1.0,
+                front_page_rank: 0,
+                resolution_secs: 0,
+            };
+            db.insert_scores(
+                added + Duration::from_secs(secs),
+                &[("t3_abc".to_string(), score)],
+            )
+            .unwrap();
+        }
+        let minute_buckets = |db: &DB| -> Vec<(i64, i64)> {
+            let conn = db.conn();
+            let mut stmt = conn
+                .prepare(
+                    "SELECT rowid, added FROM reddit_scores WHERE resolution_secs = ?1 ORDER BY added;",
+                )
+                .unwrap();
+            let rows = stmt
+                .query_map(params!(MINUTE_SECS), |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap();
+            rows.collect::<rusqlite::Result<_>>().unwrap()
+        };
+
+        let policy = RetentionPolicy {
+            raw_secs: 60,
+            minute_secs: 3600,
+            interval_secs: 60,
+        };
+        // Raw scores before 120s end up in two minute buckets
+        let removed = db
+            .downsample_scores(&policy, added + Duration::from_secs(200))
+            .unwrap();
+        assert_eq!(removed, 2);
+        let compacted = minute_buckets(&db);
+        assert_eq!(compacted.len(), 2);
+
+        // Nothing left to compact until the next minute closes
+        let removed = db
+            .downsample_scores(&policy, added + Duration::from_secs(230))
+            .unwrap();
+        assert_eq!(removed, 0);
+        assert_eq!(minute_buckets(&db), compacted);
+
+        // Only the raw scores of the minute that just closed are compacted, the existing
+        // buckets keep their rows
+        let removed = db
+            .downsample_scores(&policy, added + Duration::from_secs(260))
+            .unwrap();
+        assert_eq!(removed, 1);
+        let buckets = minute_buckets(&db);
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(&buckets[..2], &compacted[..]);
+
+        let posts = db.get_all_posts(ScoresFilter::All).unwrap();
+        let scores: Vec<(u64, u32, i32)> = posts[0]
+            .scores
+            .iter()
+            .map(|x| (x.secs_since_post_added, x.resolution_secs, x.score))
+            .collect();
+        assert_eq!(
+            scores,
+            vec![
+                (0, MINUTE_SECS, 30),
+                (60, MINUTE_SECS, 90),
+                (120, MINUTE_SECS, 150),
+                (180, 0, 180),
+                (210, 0, 210),
+            ]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lowercases_source_keys() {
+        let path = db_path("source_keys");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        db.conn()
+            .execute_batch(
+                "
+INSERT INTO watch_sources (source_key, kind, name, sort, post_limit)
+VALUES ('r/Rust', 'subreddit', 'Rust', 'hot', 10);
+INSERT INTO watch_source_posts (source_key, post_id) VALUES ('r/Rust', 't3_abc');
+INSERT INTO watch_source_exclusions (source_key, post_id) VALUES ('r/Rust', 't3_def');
+INSERT INTO watch_source_exclusions (source_key, post_id) VALUES ('r/rust', 't3_def');
+",
+            )
+            .unwrap();
+        // Version before source keys were lowercased
+        db.conn().pragma_update(None, "user_version", &9).unwrap();
+        drop(db);
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        let keys: Vec<String> = db
+            .get_watch_sources()
+            .unwrap()
+            .iter()
+            .map(|x| x.key())
+            .collect();
+        assert_eq!(keys, vec!["r/rust"]);
+        assert_eq!(
+            db.get_watch_source_posts().unwrap(),
+            vec![("r/rust".to_string(), "t3_abc".to_string())]
+        );
+        assert_eq!(
+            db.get_watch_source_exclusions().unwrap(),
+            vec![("r/rust".to_string(), "t3_def".to_string())]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn remembers_ids_of_removed_alert_rules() {
+        let path = db_path("alert_rule_ids");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(db.get_last_alert_rule_id().unwrap(), 0);
+
+        for id in 1..=3 {
+            let rule = AlertRule::new(id, None, AlertKind::Crosses, 100, 0).unwrap();
+            db.insert_alert_rule(&rule).unwrap();
+        }
+        db.delete_alert_rule(3).unwrap();
+        db.delete_alert_rule(2).unwrap();
+        drop(db);
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(db.get_alert_rules().unwrap().len(), 1);
+        assert_eq!(db.get_last_alert_rule_id().unwrap(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_schema() {
+        let path = db_path("newer");
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", &(SCHEMA_VERSION + 1))
+            .unwrap();
+        drop(conn);
+
+        assert!(DB::new(path.to_str().unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
 }
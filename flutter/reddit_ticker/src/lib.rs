@@ -1,21 +1,41 @@
 use core::time;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::Path,
-    sync::{RwLockReadGuard, RwLockWriteGuard},
+    sync::{Arc, RwLockReadGuard, RwLockWriteGuard},
     thread,
-    time::SystemTime,
+    time::{Instant, SystemTime},
 };
 
+use alerts::{alert_payload, AlertEngine, AlertKind, AlertRule};
 use anyhow::{anyhow, Result};
 use db::{DB, DB_NAME};
-use reddit::{query_page, Post};
+use polling::{PollSchedule, MIN_POLL_INTERVAL_MILLIS, POLL_TICK_MILLIS};
+use reddit::Post;
+use retention::RetentionPolicy;
 use rid::RidStore;
+use sources::{ListingSort, WatchSource, LISTING_INTERVAL_MILLIS};
 
-use crate::reddit::{query_score, Score, RESOLUTION_MILLIS};
+use crate::reddit::{
+    post_fullname, post_url, FrontPage, Score, NOT_ON_FRONT_PAGE, RESOLUTION_MILLIS,
+};
+
+pub use db::{ScoresFilter, ScoresFilterKind};
+#[cfg(any(test, feature = "stub"))]
+pub use reddit::RedditStub;
+pub use reddit::{Page, RedditClient, UreqRedditClient, UrlError};
+
+/// Separates the posts in [Reply::UpdatedScores]
+const CHANGE_SEPARATOR: char = ',';
+/// Separates the id of a post from its new score in [Reply::UpdatedScores]
+const SCORE_SEPARATOR: char = '=';
 
+mod alerts;
 mod db;
+mod polling;
 mod reddit;
+mod retention;
+mod sources;
 
 // -----------------
 // Store
@@ -28,8 +48,48 @@ pub struct Store {
 
     #[rid(skip)]
     polling: bool,
+    /// Shared with the threads polling and compacting scores so that they can use it without
+    /// holding a lock on the Store
+    #[rid(skip)]
+    db: Option<Arc<DB>>,
+    #[rid(skip)]
+    client: Arc<dyn RedditClient>,
+    #[rid(skip)]
+    scores_filter: ScoresFilter,
+    #[rid(skip)]
+    retention: RetentionPolicy,
+    #[rid(skip)]
+    poll_interval_millis: u64,
+    #[rid(skip)]
+    adaptive_polling: bool,
+    /// Posts without a schedule are polled right away
     #[rid(skip)]
-    db: Option<DB>,
+    poll_schedules: HashMap<String, PollSchedule>,
+    /// Set while reddit asked us to back off
+    #[rid(skip)]
+    throttled: bool,
+    /// Ids of posts that are about to be watched with the ids of the requests waiting for them
+    #[rid(skip)]
+    pending_watches: HashMap<String, Vec<u64>>,
+    /// Subreddits and users whose posts are watched automatically keyed by [WatchSource::key]
+    #[rid(skip)]
+    watch_sources: HashMap<String, WatchSource>,
+    /// Ids of the posts each source added, posts not added by any source are never pruned
+    #[rid(skip)]
+    source_posts: HashMap<String, HashSet<String>>,
+    /// Ids of the posts of each source the user stopped watching, these aren't added again
+    /// while the source is watched
+    #[rid(skip)]
+    excluded_posts: HashMap<String, HashSet<String>>,
+    /// Sources without a scheduled listing are listed right away
+    #[rid(skip)]
+    next_listings: HashMap<String, Instant>,
+    #[rid(skip)]
+    alerts: AlertEngine,
+    /// Front pages of the subreddits of watched posts keyed by subreddit, they are listed
+    /// again once they are older than [LISTING_INTERVAL_MILLIS]
+    #[rid(skip)]
+    front_pages: HashMap<String, FrontPage>,
 }
 
 impl RidStore<Msg> for Store {
@@ -38,16 +98,32 @@ impl RidStore<Msg> for Store {
             posts: HashMap::new(),
             polling: false,
             db: None,
+            client: Arc::new(UreqRedditClient::new()),
+            scores_filter: ScoresFilter::All,
+            retention: RetentionPolicy::default(),
+            poll_interval_millis: RESOLUTION_MILLIS,
+            adaptive_polling: false,
+            poll_schedules: HashMap::new(),
+            throttled: false,
+            pending_watches: HashMap::new(),
+            watch_sources: HashMap::new(),
+            source_posts: HashMap::new(),
+            excluded_posts: HashMap::new(),
+            next_listings: HashMap::new(),
+            alerts: AlertEngine::default(),
+            front_pages: HashMap::new(),
         }
     }
 
     fn update(&mut self, req_id: u64, msg: Msg) {
         match msg {
             Msg::Initialize(app_dir) => {
-                // Guard against more than one polling thread
+                // Guard against more than one polling, retention and listing thread
                 if !self.polling {
                     self.polling = true;
                     poll_posts();
+                    downsample_scores();
+                    list_watch_sources();
                 }
 
                 if self.db.is_none() {
@@ -58,7 +134,7 @@ impl RidStore<Msg> for Store {
 
                     match DB::new(&db_path) {
                         Ok(db) => {
-                            self.db = Some(db);
+                            self.db = Some(Arc::new(db));
                             rid::log_info!("Initialized Database at '{}'", db_path);
                         }
                         Err(err) => {
@@ -70,8 +146,10 @@ impl RidStore<Msg> for Store {
                     }
                 }
 
+                // Posts are compacted according to the stored policy right after loading them
+                self.load_retention();
                 if let Some(db) = &self.db {
-                    self.posts = match db.get_all_posts() {
+                    self.posts = match db.get_all_posts(self.scores_filter) {
                         Ok(posts) => {
                             let mut map = HashMap::<String, Post>::new();
                             for post in posts {
@@ -84,25 +162,150 @@ impl RidStore<Msg> for Store {
                             HashMap::new()
                         }
                     };
+                    // Posts loaded from the Database are only compacted up to the last time
+                    // the retention thread ran
+                    self.downsample_posts(SystemTime::now());
                 }
+                self.load_watch_sources();
+                self.load_alert_rules();
 
                 rid::post(Reply::Initialized(req_id));
             }
 
-            Msg::StartWatching(url) => start_watching(req_id, url),
+            Msg::StartWatching(url) => self.start_watching(req_id, url),
             Msg::StopWatching(id) => {
-                self.posts.remove(&id);
-                if let Some(db) = &self.db {
-                    match db.delete_post(&id) {
-                        Ok(rows) => {
-                            rid::log_debug!("Removed post and {} scores from Database", rows - 1);
-                        }
-                        Err(err) => {
-                            rid::error!("Failed to delete post from Database", err);
+                self.exclude_from_sources(&id);
+                self.remove_post(&id);
+                rid::post(Reply::StoppedWatching(req_id, id));
+            }
+
+            Msg::WatchSubreddit(name, sort, limit) => {
+                self.watch_source(req_id, WatchSource::subreddit(&name, sort, limit))
+            }
+            Msg::WatchUser(name) => self.watch_source(req_id, WatchSource::user(&name)),
+            Msg::UnwatchSource(key) => self.unwatch_source(req_id, key),
+
+            Msg::AddAlertRule(post_id, kind, threshold, window_secs) => {
+                self.add_alert_rule(req_id, post_id, kind, threshold, window_secs)
+            }
+            Msg::RemoveAlertRule(rule_id) => {
+                if self.alerts.remove_rule(rule_id).is_none() {
+                    rid::post(Reply::FailedRequest(
+                        req_id,
+                        format!("No alert rule with id {}", rule_id),
+                    ));
+                } else {
+                    if let Some(db) = &self.db {
+                        if let Err(err) = db.delete_alert_rule(rule_id) {
+                            rid::error!("Failed to delete alert rule from Database", err);
                         }
-                    };
+                    }
+                    rid::post(Reply::RemovedAlertRule(req_id));
+                }
+            }
+
+            Msg::SetRedditBaseUrl(base_url) => {
+                let client = if base_url.is_empty() {
+                    UreqRedditClient::new()
+                } else {
+                    UreqRedditClient::with_base_url(&base_url)
                 };
-                rid::post(Reply::StoppedWatching(req_id, id));
+                self.set_reddit_client(Arc::new(client));
+                rid::log_info!("Querying reddit at '{}'", base_url);
+                rid::post(Reply::SetRedditBaseUrl(req_id));
+            }
+
+            Msg::SetPollInterval(millis) => {
+                if millis < MIN_POLL_INTERVAL_MILLIS {
+                    rid::post(Reply::FailedRequest(
+                        req_id,
+                        format!(
+                            "Poll interval needs to be at least {}ms",
+                            MIN_POLL_INTERVAL_MILLIS
+                        ),
+                    ));
+                } else {
+                    self.poll_interval_millis = millis;
+                    let now = Instant::now();
+                    for schedule in self.poll_schedules.values_mut() {
+                        schedule.reschedule(now, millis);
+                    }
+                    rid::post(Reply::SetPollInterval(req_id));
+                }
+            }
+            Msg::SetPostPollInterval(id, millis) => {
+                if !self.posts.contains_key(&id) {
+                    rid::post(Reply::FailedRequest(
+                        req_id,
+                        format!("Not watching post with id '{}'", id),
+                    ));
+                } else if millis != 0 && millis < MIN_POLL_INTERVAL_MILLIS {
+                    rid::post(Reply::FailedRequest(
+                        req_id,
+                        format!(
+                            "Poll interval needs to be at least {}ms",
+                            MIN_POLL_INTERVAL_MILLIS
+                        ),
+                    ));
+                } else {
+                    let interval_millis = self.poll_interval_millis;
+                    let schedule = self
+                        .poll_schedules
+                        .entry(id.clone())
+                        .or_insert_with(|| PollSchedule::new(interval_millis));
+                    schedule.override_millis = if millis == 0 { None } else { Some(millis) };
+                    schedule.reschedule(Instant::now(), interval_millis);
+                    rid::post(Reply::SetPostPollInterval(req_id, id));
+                }
+            }
+            Msg::SetAdaptivePolling(adaptive) => {
+                self.adaptive_polling = adaptive;
+                let now = Instant::now();
+                let interval_millis = self.poll_interval_millis;
+                for schedule in self.poll_schedules.values_mut() {
+                    schedule.reschedule(now, interval_millis);
+                }
+                rid::post(Reply::SetAdaptivePolling(req_id));
+            }
+
+            Msg::SetScoresFilter(kind, value) => {
+                if self.polling {
+                    rid::post(Reply::FailedRequest(
+                        req_id,
+                        "Scores filter needs to be set before initializing".to_string(),
+                    ));
+                } else {
+                    match ScoresFilter::new(kind, value, SystemTime::now()) {
+                        Ok(filter) => {
+                            self.set_scores_filter(filter);
+                            rid::post(Reply::SetScoresFilter(req_id));
+                        }
+                        Err(err) => rid::post(Reply::FailedRequest(req_id, err.to_string())),
+                    }
+                }
+            }
+
+            Msg::SetRawScoresRetentionSecs(secs) => {
+                self.retention.raw_secs = secs;
+                self.persist_retention();
+                rid::post(Reply::SetRawScoresRetentionSecs(req_id));
+            }
+            Msg::SetMinuteScoresRetentionSecs(secs) => {
+                self.retention.minute_secs = secs;
+                self.persist_retention();
+                rid::post(Reply::SetMinuteScoresRetentionSecs(req_id));
+            }
+            Msg::SetRetentionIntervalSecs(secs) => {
+                if secs == 0 {
+                    rid::post(Reply::FailedRequest(
+                        req_id,
+                        "Retention interval needs to be at least one second".to_string(),
+                    ));
+                } else {
+                    self.retention.interval_secs = secs;
+                    self.persist_retention();
+                    rid::post(Reply::SetRetentionIntervalSecs(req_id));
+                }
             }
         }
     }
@@ -115,17 +318,445 @@ impl Store {
     fn write() -> RwLockWriteGuard<'static, Store> {
         store::write()
     }
+
+    /// Replaces the client used to query pages and scores, i.e. to run against a [RedditStub].
+    pub fn set_reddit_client(&mut self, client: Arc<dyn RedditClient>) {
+        self.client = client;
+    }
+
+    /// Limits the scores loaded from the Database on [Msg::Initialize] in order to speed up
+    /// startup when lots of scores were recorded.
+    pub fn set_scores_filter(&mut self, filter: ScoresFilter) {
+        self.scores_filter = filter;
+    }
+
+    /// Watches the post the `url` refers to unless it is watched already.
+    /// Requests for a post that is about to be watched are answered once that completes.
+    fn start_watching(&mut self, req_id: u64, url: String) {
+        let fullname = match post_fullname(&url) {
+            Ok(fullname) => fullname,
+            Err(err) => {
+                rid::post(Reply::FailedRequest(req_id, err.to_string()));
+                return;
+            }
+        };
+
+        if self.posts.contains_key(&fullname) {
+            // Explicitly watching a post added by a source keeps it from being pruned
+            for (key, post_ids) in self.source_posts.iter_mut() {
+                if post_ids.remove(&fullname) {
+                    if let Some(db) = &self.db {
+                        if let Err(err) = db.delete_watch_source_post(key, &fullname) {
+                            rid::error!("Failed to remove post from watch source", err);
+                        }
+                    }
+                }
+            }
+            rid::post(Reply::AlreadyWatching(req_id, fullname));
+        } else if let Some(req_ids) = self.pending_watches.get_mut(&fullname) {
+            req_ids.push(req_id);
+        } else {
+            self.pending_watches.insert(fullname.clone(), vec![req_id]);
+            start_watching(fullname);
+        }
+    }
+
+    /// Stops watching the post and removes it including its scores from the Database.
+    fn remove_post(&mut self, id: &str) {
+        self.posts.remove(id);
+        self.poll_schedules.remove(id);
+        self.alerts.remove_post(id);
+        for post_ids in self.source_posts.values_mut() {
+            post_ids.remove(id);
+        }
+        if let Some(db) = &self.db {
+            match db.delete_post(id) {
+                Ok(rows) => {
+                    rid::log_debug!(
+                        "Removed post and {} scores from Database",
+                        rows.saturating_sub(1)
+                    );
+                }
+                Err(err) => {
+                    rid::error!("Failed to delete post from Database", err);
+                }
+            };
+        };
+    }
+
+    /// Time until the next post is due to be polled, at most [POLL_TICK_MILLIS] so that newly
+    /// watched posts and changed intervals are picked up in time.
+    fn time_until_next_poll(&self, now: Instant) -> time::Duration {
+        let tick = time::Duration::from_millis(POLL_TICK_MILLIS);
+        self.posts
+            .keys()
+            .map(|id| match self.poll_schedules.get(id) {
+                Some(schedule) => schedule.next_poll.saturating_duration_since(now),
+                None => time::Duration::from_millis(0),
+            })
+            .min()
+            .map_or(tick, |until_next_poll| until_next_poll.min(tick))
+    }
+
+    /// Compacts the scores of all posts according to the retention policy.
+    /// Returns `true` if any scores were compacted.
+    fn downsample_posts(&mut self, now: SystemTime) -> bool {
+        let retention = self.retention;
+        let mut compacted = false;
+        for post in self.posts.values_mut() {
+            let secs_since_post_added =
+                now.duration_since(post.added).unwrap_or_default().as_secs();
+            compacted |= retention.downsample(&mut post.scores, secs_since_post_added);
+        }
+        compacted
+    }
+
+    fn load_retention(&mut self) {
+        if let Some(db) = &self.db {
+            match db.get_retention() {
+                Ok(Some(retention)) => self.retention = retention,
+                Ok(None) => {}
+                Err(err) => rid::error!("Failed to retrieve retention policy", err),
+            }
+        }
+    }
+
+    fn persist_retention(&self) {
+        if let Some(db) = &self.db {
+            if let Err(err) = db.upsert_retention(&self.retention) {
+                rid::error!("Failed to store retention policy", err);
+            }
+        }
+    }
+}
+
+// -----------------
+// Watch Sources
+// -----------------
+impl Store {
+    fn load_watch_sources(&mut self) {
+        if let Some(db) = &self.db {
+            match db.get_watch_sources() {
+                Ok(sources) => {
+                    for source in sources {
+                        self.watch_sources.insert(source.key(), source);
+                    }
+                }
+                Err(err) => rid::error!("Failed to retrieve watch sources", err),
+            }
+            match db.get_watch_source_posts() {
+                Ok(source_posts) => {
+                    for (key, post_id) in source_posts {
+                        self.source_posts.entry(key).or_default().insert(post_id);
+                    }
+                }
+                Err(err) => rid::error!("Failed to retrieve posts of watch sources", err),
+            }
+            match db.get_watch_source_exclusions() {
+                Ok(excluded_posts) => {
+                    for (key, post_id) in excluded_posts {
+                        self.excluded_posts.entry(key).or_default().insert(post_id);
+                    }
+                }
+                Err(err) => rid::error!("Failed to retrieve excluded posts", err),
+            }
+        }
+    }
+
+    fn watch_source(&mut self, req_id: u64, source: Result<WatchSource>) {
+        let source = match source {
+            Ok(source) => source,
+            Err(err) => {
+                rid::post(Reply::FailedRequest(req_id, err.to_string()));
+                return;
+            }
+        };
+
+        let key = source.key();
+        if let Some(db) = &self.db {
+            if let Err(err) = db.insert_watch_source(&source) {
+                rid::error!("Failed to add watch source to Database", err);
+            }
+        }
+        // Listed right away, also when the sort or limit of a watched source changed
+        self.next_listings.remove(&key);
+        self.watch_sources.insert(key.clone(), source);
+        rid::post(Reply::WatchingSource(req_id, key));
+    }
+
+    /// Stops watching the source and all posts it added unless another source added them too.
+    fn unwatch_source(&mut self, req_id: u64, key: String) {
+        let key = key.to_lowercase();
+        if self.watch_sources.remove(&key).is_none() {
+            rid::post(Reply::FailedRequest(
+                req_id,
+                format!("Not watching '{}'", key),
+            ));
+            return;
+        }
+        self.next_listings.remove(&key);
+        self.excluded_posts.remove(&key);
+        if let Some(db) = &self.db {
+            if let Err(err) = db.delete_watch_source(&key) {
+                rid::error!("Failed to delete watch source from Database", err);
+            }
+        }
+
+        let post_ids = self.source_posts.remove(&key).unwrap_or_default();
+        for id in post_ids {
+            if !self.is_added_by_source(&id) {
+                self.remove_post(&id);
+            }
+        }
+        rid::post(Reply::UnwatchedSource(req_id, key));
+    }
+
+    /// Watches the `listed` posts of the source and stops watching the ones it added before
+    /// which are no longer listed. Returns `true` if any posts were added or removed.
+    fn update_source_posts(&mut self, key: &str, listed: Vec<Page>) -> bool {
+        let listed_ids: HashSet<String> = listed.iter().map(|page| page.id.clone()).collect();
+        let pruned: Vec<String> = self
+            .source_posts
+            .get(key)
+            .map(|post_ids| post_ids.difference(&listed_ids).cloned().collect())
+            .unwrap_or_default();
+        let mut changed = !pruned.is_empty();
+
+        for id in pruned {
+            if let Some(post_ids) = self.source_posts.get_mut(key) {
+                post_ids.remove(&id);
+            }
+            if let Some(db) = &self.db {
+                if let Err(err) = db.delete_watch_source_post(key, &id) {
+                    rid::error!("Failed to remove post from watch source", err);
+                }
+            }
+            if !self.is_added_by_source(&id) {
+                self.remove_post(&id);
+            }
+        }
+
+        // Posts that are gone from the listing can't be added again
+        let unlisted: Vec<String> = self
+            .excluded_posts
+            .get(key)
+            .map(|post_ids| post_ids.difference(&listed_ids).cloned().collect())
+            .unwrap_or_default();
+        for id in unlisted {
+            if let Some(post_ids) = self.excluded_posts.get_mut(key) {
+                post_ids.remove(&id);
+            }
+            if let Some(db) = &self.db {
+                if let Err(err) = db.delete_watch_source_exclusion(key, &id) {
+                    rid::error!("Failed to remove excluded post from watch source", err);
+                }
+            }
+        }
+
+        let added = SystemTime::now();
+        let excluded = self.excluded_posts.get(key);
+        let listed: Vec<Page> = listed
+            .into_iter()
+            .filter(|page| !excluded.map(|ids| ids.contains(&page.id)).unwrap_or(false))
+            .collect();
+        for page in listed {
+            let is_watched = self.posts.contains_key(&page.id);
+            // Posts that are watched explicitly are never taken over by a source
+            if (is_watched && !self.is_added_by_source(&page.id))
+                || self.pending_watches.contains_key(&page.id)
+            {
+                continue;
+            }
+            let is_new = self
+                .source_posts
+                .entry(key.to_string())
+                .or_default()
+                .insert(page.id.clone());
+            if !is_new {
+                continue;
+            }
+            changed = true;
+
+            let post = Post {
+                added,
+                id: page.id,
+                title: page.title,
+                url: page.url,
+                scores: vec![],
+            };
+            if let Some(db) = &self.db {
+                if let Err(err) = db.insert_watch_source_post(key, &post.id) {
+                    rid::error!("Failed to add post to watch source", err);
+                }
+                if !is_watched {
+                    if let Err(err) = db.insert_post(&post) {
+                        rid::error!("Failed to insert post", err.to_string());
+                    }
+                }
+            }
+            if !is_watched {
+                self.posts.insert(post.id.clone(), post);
+            }
+        }
+        changed
+    }
+
+    /// Keeps the sources that added the post from adding it again once the user stopped
+    /// watching it.
+    fn exclude_from_sources(&mut self, id: &str) {
+        for (key, post_ids) in self.source_posts.iter() {
+            if !post_ids.contains(id) {
+                continue;
+            }
+            self.excluded_posts
+                .entry(key.clone())
+                .or_default()
+                .insert(id.to_string());
+            if let Some(db) = &self.db {
+                if let Err(err) = db.insert_watch_source_exclusion(key, id) {
+                    rid::error!("Failed to exclude post from watch source", err);
+                }
+            }
+        }
+    }
+
+    fn is_added_by_source(&self, id: &str) -> bool {
+        self.source_posts
+            .values()
+            .any(|post_ids| post_ids.contains(id))
+    }
+}
+
+// -----------------
+// Alert Rules
+// -----------------
+impl Store {
+    fn load_alert_rules(&mut self) {
+        if let Some(db) = &self.db {
+            match db.get_alert_rules() {
+                Ok(rules) => {
+                    for rule in rules {
+                        self.alerts.add_rule(rule);
+                    }
+                }
+                Err(err) => rid::error!("Failed to retrieve alert rules", err),
+            }
+            match db.get_last_alert_rule_id() {
+                Ok(id) => self.alerts.set_last_rule_id(id),
+                Err(err) => rid::error!("Failed to retrieve last alert rule id", err),
+            }
+        }
+    }
+
+    fn add_alert_rule(
+        &mut self,
+        req_id: u64,
+        post_id: String,
+        kind: AlertKind,
+        threshold: i32,
+        window_secs: u64,
+    ) {
+        let post_id = if post_id.is_empty() {
+            None
+        } else if self.posts.contains_key(&post_id) {
+            Some(post_id)
+        } else {
+            rid::post(Reply::FailedRequest(
+                req_id,
+                format!("Not watching post with id '{}'", post_id),
+            ));
+            return;
+        };
+
+        let id = self.alerts.next_rule_id();
+        match AlertRule::new(id, post_id, kind, threshold, window_secs) {
+            Ok(rule) => {
+                if let Some(db) = &self.db {
+                    if let Err(err) = db.insert_alert_rule(&rule) {
+                        rid::error!("Failed to add alert rule to Database", err);
+                    }
+                }
+                self.alerts.add_rule(rule);
+                rid::post(Reply::AddedAlertRule(req_id, id.to_string()));
+            }
+            Err(err) => rid::post(Reply::FailedRequest(req_id, err.to_string())),
+        }
+    }
+}
+
+// -----------------
+// Score Accessors
+// -----------------
+#[rid::export]
+#[rid::structs(Score)]
+impl Store {
+    /// Returns the scores of the post following the first `len` ones, which allows the UI to
+    /// append the scores announced via [Reply::UpdatedScores] to what it rendered already.
+    /// Indexes change once scores are compacted, see [Reply::CompactedScores].
+    #[rid::export]
+    fn scores_after(&self, post_id: String, len: u32) -> Vec<&Score> {
+        match self.posts.get(&post_id) {
+            Some(post) => post.scores.iter().skip(len as usize).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the watched posts ordered by the time they were added, most recent first.
+    #[rid::export]
+    #[rid::structs(Post)]
+    fn posts_by_added(&self) -> Vec<&Post> {
+        let mut posts: Vec<&Post> = self.posts.values().collect();
+        posts.sort_by(|a, b| b.added.cmp(&a.added).then_with(|| a.id.cmp(&b.id)));
+        posts
+    }
 }
 
 // -----------------
 // Message
 // -----------------
 #[rid::message(Reply)]
+#[rid::enums(ListingSort, AlertKind, ScoresFilterKind)]
 enum Msg {
     Initialize(String),
 
+    /// Accepts post urls in any form reddit uses, short links and bare post ids
     StartWatching(String),
     StopWatching(String),
+
+    /// Watches the top posts of the subreddit with the given name up to the given limit,
+    /// watching the same subreddit again updates its sort and limit
+    WatchSubreddit(String, ListingSort, u32),
+    /// Watches the posts the user with the given name submitted most recently
+    WatchUser(String),
+    /// Stops watching a subreddit or user by its key, i.e. `r/rust` or `u/spez`
+    UnwatchSource(String),
+
+    /// Adds a rule for the post with the given id or all posts if it is empty, followed by the
+    /// threshold and the window in secs used by [AlertKind::Gains] and [AlertKind::Drops]
+    AddAlertRule(String, AlertKind, i32, u64),
+    RemoveAlertRule(u32),
+
+    /// Queries a different server than reddit, i.e. a `RedditStub` built with the `stub`
+    /// feature, reset via an empty url
+    SetRedditBaseUrl(String),
+
+    /// Millis between polls of posts without their own interval, defaults to [RESOLUTION_MILLIS]
+    SetPollInterval(u64),
+    /// Polls the post with the given id at a fixed interval in millis, reset via `0`
+    SetPostPollInterval(String, u64),
+    /// Backs off polling posts whose score doesn't change and speeds up for fast moving ones
+    SetAdaptivePolling(bool),
+
+    /// Limits the scores loaded on [Msg::Initialize] to the given number of latest scores or
+    /// the given secs, needs to be sent before it
+    SetScoresFilter(ScoresFilterKind, u64),
+
+    /// Scores older than this are compacted into per minute buckets
+    SetRawScoresRetentionSecs(u64),
+    /// Per minute buckets older than this are compacted into per hour buckets
+    SetMinuteScoresRetentionSecs(u64),
+    /// Takes effect once the currently scheduled retention run completed
+    SetRetentionIntervalSecs(u64),
 }
 
 // -----------------
@@ -136,31 +767,85 @@ enum Reply {
     Initialized(u64),
 
     StartedWatching(u64, String),
+    /// Includes the id of the post that was watched already
+    AlreadyWatching(u64, String),
     StoppedWatching(u64, String),
+    /// Includes the key of the source, i.e. `r/rust`
+    WatchingSource(u64, String),
+    UnwatchedSource(u64, String),
+    /// Includes the id of the added rule
+    AddedAlertRule(u64, String),
+    RemovedAlertRule(u64),
     FailedRequest(u64, String),
 
-    UpdatedScores,
+    SetRedditBaseUrl(u64),
+    SetPollInterval(u64),
+    SetPostPollInterval(u64, String),
+    SetAdaptivePolling(u64),
+    SetScoresFilter(u64),
+    SetRawScoresRetentionSecs(u64),
+    SetMinuteScoresRetentionSecs(u64),
+    SetRetentionIntervalSecs(u64),
+
+    /// Includes the ids of the posts that got a new score each followed by that score,
+    /// i.e. `t3_abc123=42,t3_def456=7`
+    UpdatedScores(String),
+    /// Scores of posts were compacted which changes their indexes, all scores need to be reloaded
+    CompactedScores,
+    /// Posts of the source with the given key were added or removed
+    UpdatedSource(String),
+    /// Includes the ids of the rule that fired and the post, i.e. `3:t3_abc123`
+    AlertTriggered(String),
+    /// Reddit asked us to back off, includes a message for the user
+    Throttled(String),
+    Unthrottled,
 }
 
 // -----------------
 // Start watching Post
 // -----------------
-fn start_watching(req_id: u64, url: String) {
-    thread::spawn(move || match try_start_watching(url) {
-        Ok(post) => {
-            let id = post.id.clone();
-            Store::write().posts.insert(id.clone(), post);
-            rid::post(Reply::StartedWatching(req_id, id))
+fn start_watching(fullname: String) {
+    thread::spawn(move || {
+        let result = try_start_watching(&fullname);
+
+        let (req_ids, id) = {
+            let mut store = Store::write();
+            let req_ids = store.pending_watches.remove(&fullname).unwrap_or_default();
+            match result {
+                Ok(post) => {
+                    let id = post.id.clone();
+                    // Never replace a watched post since that would reset its score history
+                    store.posts.entry(id.clone()).or_insert(post);
+                    (req_ids, Ok(id))
+                }
+                Err(err) => (req_ids, Err(err)),
+            }
+        };
+
+        // The first request started watching the post, all others were duplicates of it
+        for (idx, req_id) in req_ids.into_iter().enumerate() {
+            match &id {
+                Ok(id) if idx == 0 => rid::post(Reply::StartedWatching(req_id, id.clone())),
+                Ok(id) => rid::post(Reply::AlreadyWatching(req_id, id.clone())),
+                Err(err) => rid::post(Reply::FailedRequest(req_id, err.to_string())),
+            }
         }
-        Err(err) => rid::post(Reply::FailedRequest(req_id, err.to_string())),
     });
 }
 
-fn try_start_watching(url: String) -> Result<Post> {
-    let page = query_page(&url)
-        .map_err(|err| anyhow!("Failed to get valid page data: {}\nError: {} ", url, err))?;
+fn try_start_watching(fullname: &str) -> Result<Post> {
+    let client = Store::read().client.clone();
+    let page = client.query_page(&post_url(fullname));
+    update_throttled(client.as_ref());
+    let page = page.map_err(|err| {
+        anyhow!(
+            "Failed to get valid page data for post: {}\nError: {} ",
+            fullname,
+            err
+        )
+    })?;
 
-    rid::log_debug!("Got page for url '{}' with id '{}'.", url, page.id);
+    rid::log_debug!("Got page for post '{}' with id '{}'.", fullname, page.id);
 
     let added = SystemTime::now();
     let post = Post {
@@ -185,60 +870,439 @@ fn try_start_watching(url: String) -> Result<Post> {
 fn poll_posts() {
     rid::log_debug!("Creating thread to poll post data");
     thread::spawn(move || loop {
-        // First we query all posts and only take a write lock on the store once we have all the
-        // data in order to limit the amount of time that the UI or other threads cannot access the
-        // store.
+        thread::sleep(poll_cycle());
+    });
+}
 
-        // In order to release the read lock on the store immediately, we clone the post ids.
-        let post_ids: Vec<_> = { Store::read().posts.keys().cloned().collect() };
-        let scores: Vec<_> = post_ids
-            .into_iter()
-            .map(|id| {
-                let score = query_score(&id);
-                (id, score)
-            })
-            // Filter out all cases where we couldn't update the score and send an error so that we
-            // can log the problem and alert the user
-            .filter_map(|(id, score_res)| match score_res {
-                Ok(score) => Some((id, score)),
-                Err(err) => {
-                    rid::error!("Failed to update score for a post", err.to_string());
-                    None
-                }
+/// Polls the scores of all posts that are due and returns how long to wait until the next cycle.
+fn poll_cycle() -> time::Duration {
+    // First we query all posts and only take a write lock on the store once we have all the
+    // data in order to limit the amount of time that the UI or other threads cannot access the
+    // store.
+
+    // In order to release the read lock on the store immediately, we clone the ids of the
+    // posts that are due.
+    let now = Instant::now();
+    let (post_ids, client): (Vec<_>, _) = {
+        let store = Store::read();
+        let post_ids = store
+            .posts
+            .keys()
+            .filter(|id| {
+                store
+                    .poll_schedules
+                    .get(*id)
+                    .map(|schedule| schedule.is_due(now))
+                    .unwrap_or(true)
             })
+            .cloned()
             .collect();
+        (post_ids, store.client.clone())
+    };
+    if post_ids.is_empty() {
+        return Store::read().time_until_next_poll(now);
+    }
+    // Querying while reddit asked us to back off would only result in errors
+    if let Some(throttled_for) = update_throttled(client.as_ref()) {
+        return throttled_for.min(time::Duration::from_millis(POLL_TICK_MILLIS));
+    }
 
-        {
-            // Aquire a write lock on the store once and make sure it gets dropped (at the end of
-            // this block) when we no longer need it
-            let mut store = Store::write();
-            for (id, score) in scores {
-                // A post could have been removed in between getting the post ids and aquiring
-                // the write lock.
-                if !store.posts.contains_key(&id) {
-                    continue;
+    let (mut infos, queried) = match client.query_post_infos(&post_ids) {
+        Ok(infos) => (infos, true),
+        Err(err) => {
+            rid::error!("Failed to update scores", err.to_string());
+            (HashMap::new(), false)
+        }
+    };
+
+    // Posts are ranked against their subreddit's front page which is only listed again once
+    // the one we have is stale, in between the rank is taken from the one we have. If listing
+    // fails the posts are ranked against the stale one or treated as not being on the front page.
+    let front_page_max_age = time::Duration::from_millis(LISTING_INTERVAL_MILLIS);
+    let subreddits: HashSet<String> = infos.values().map(|x| x.subreddit.clone()).collect();
+    let stale_subreddits: Vec<&String> = {
+        let store = Store::read();
+        subreddits
+            .iter()
+            .filter(|subreddit| {
+                store
+                    .front_pages
+                    .get(*subreddit)
+                    .map(|front_page| front_page.is_stale(now, front_page_max_age))
+                    .unwrap_or(true)
+            })
+            .collect()
+    };
+    let listed_front_pages: Vec<(String, FrontPage)> = stale_subreddits
+        .into_iter()
+        .filter_map(|subreddit| match client.query_front_page(subreddit) {
+            Ok(ids) => {
+                let front_page = FrontPage {
+                    ids,
+                    listed_at: Instant::now(),
+                };
+                Some((subreddit.clone(), front_page))
+            }
+            Err(err) => {
+                rid::error!(
+                    format!("Failed to get front page of r/{}", subreddit),
+                    err.to_string()
+                );
+                None
+            }
+        })
+        .collect();
+    update_throttled(client.as_ref());
+
+    // Filter out all cases where we couldn't update the score and send an error so that we
+    // can log the problem and alert the user
+    let scores: Vec<_> = post_ids
+        .iter()
+        .filter_map(|id| match infos.remove(id) {
+            Some(info) => Some((id.clone(), info)),
+            None => {
+                // Already reported above if none of the scores could be queried
+                if queried {
+                    rid::error!("Failed to update score for a post", id);
                 }
+                None
+            }
+        })
+        .collect();
 
-                let time_stamp = SystemTime::now();
-                let post = &mut store.posts.get_mut(&id).unwrap();
-                let secs_since_post_added = time_stamp
-                    .duration_since(post.added)
-                    .expect("Getting duration")
-                    .as_secs();
+    let time_stamp = SystemTime::now();
+    let (added_scores, alerts, sleep, db): (Vec<(String, Score)>, Vec<String>, _, _) = {
+        // Aquire a write lock on the store once and make sure it gets dropped (at the end of
+        // this block) when we no longer need it
+        let mut store = Store::write();
+        // Front pages of subreddits that no post was polled for are dropped once they are stale
+        store.front_pages.retain(|subreddit, front_page| {
+            subreddits.contains(subreddit) || !front_page.is_stale(now, front_page_max_age)
+        });
+        store.front_pages.extend(listed_front_pages);
+
+        let mut added_scores = Vec::with_capacity(scores.len());
+        let mut score_deltas = HashMap::with_capacity(scores.len());
+        for (id, info) in scores {
+            // A post could have been removed in between getting the post ids and aquiring
+            // the write lock.
+            if !store.posts.contains_key(&id) {
+                continue;
+            }
 
-                post.scores.push(Score {
-                    secs_since_post_added,
-                    score,
-                });
+            let front_page_rank = store
+                .front_pages
+                .get(&info.subreddit)
+                .map_or(NOT_ON_FRONT_PAGE, |front_page| front_page.rank(&id));
+            let post = store.posts.get_mut(&id).unwrap();
+            // Treat scores as taken right when the post was added if the clock was set back
+            let secs_since_post_added = time_stamp
+                .duration_since(post.added)
+                .unwrap_or_default()
+                .as_secs();
+
+            let score = Score {
+                secs_since_post_added,
+                score: info.score,
+                min_score: info.score,
+                max_score: info.score,
+                resolution_secs: 0,
+                num_comments: info.num_comments,
+                upvote_ratio: info.upvote_ratio,
+                front_page_rank,
+            };
+
+            if let Some(last) = post.scores.last() {
+                score_deltas.insert(id.clone(), score.score - last.score);
+            }
+            post.scores.push(score.clone());
+            added_scores.push((id, score));
+        }
 
-                if let Some(db) = &store.db.as_ref() {
-                    if let Err(err) = db.insert_score(&id, time_stamp, score) {
-                        rid::error!("Failed to add score for post", err.to_string());
+        // Posts whose score couldn't be updated are polled again at the regular interval
+        let (interval_millis, adaptive) = (store.poll_interval_millis, store.adaptive_polling);
+        let now = Instant::now();
+        for id in post_ids {
+            if !store.posts.contains_key(&id) {
+                continue;
+            }
+            let score_delta = score_deltas.get(&id).copied();
+            store
+                .poll_schedules
+                .entry(id)
+                .or_insert_with(|| PollSchedule::new(interval_millis))
+                .polled(now, score_delta, interval_millis, adaptive);
+        }
+
+        // Rules are evaluated once all scores of this cycle were added
+        let store = &mut *store;
+        let mut alerts = vec![];
+        for (id, _) in &added_scores {
+            if let Some(post) = store.posts.get(id) {
+                for rule_id in store.alerts.evaluate(id, &post.scores) {
+                    alerts.push(alert_payload(rule_id, id));
+                }
+            }
+        }
+        (
+            added_scores,
+            alerts,
+            store.time_until_next_poll(now),
+            store.db.clone(),
+        )
+    };
+
+    // Persisted once the write lock was released, scores of posts that were removed in between
+    // are skipped by the Database
+    if let Some(db) = db {
+        if let Err(err) = db.insert_scores(time_stamp, &added_scores) {
+            rid::error!("Failed to add scores for posts", err.to_string());
+        }
+    }
+
+    if !added_scores.is_empty() {
+        rid::post(Reply::UpdatedScores(score_changes_payload(&added_scores)));
+    }
+    for alert in alerts {
+        rid::post(Reply::AlertTriggered(alert));
+    }
+    sleep
+}
+
+/// Encodes the payload of [Reply::UpdatedScores].
+fn score_changes_payload(added_scores: &[(String, Score)]) -> String {
+    added_scores
+        .iter()
+        .map(|(id, score)| format!("{}{}{}", id, SCORE_SEPARATOR, score.score))
+        .collect::<Vec<_>>()
+        .join(&CHANGE_SEPARATOR.to_string())
+}
+
+/// Lets the UI know when reddit starts or stops throttling our requests.
+fn update_throttled(client: &dyn RedditClient) -> Option<time::Duration> {
+    let throttled_for = client.throttled_for();
+    if Store::read().throttled == throttled_for.is_some() {
+        return throttled_for;
+    }
+
+    Store::write().throttled = throttled_for.is_some();
+    match throttled_for {
+        Some(throttled_for) => {
+            rid::log_warn!("Throttled for {}s", throttled_for.as_secs());
+            rid::post(Reply::Throttled(format!(
+                "Reddit is rate limiting the ticker, scores are updated again in {}s",
+                throttled_for.as_secs().max(1)
+            )));
+        }
+        None => rid::post(Reply::Unthrottled),
+    }
+    throttled_for
+}
+
+// -----------------
+// Listing Watch Sources
+// -----------------
+fn list_watch_sources() {
+    rid::log_debug!("Creating thread to list watch sources");
+    thread::spawn(move || loop {
+        let now = Instant::now();
+        let (sources, client): (Vec<WatchSource>, _) = {
+            let store = Store::read();
+            let sources = store
+                .watch_sources
+                .iter()
+                .filter(|(key, _)| {
+                    store
+                        .next_listings
+                        .get(*key)
+                        .map(|next_listing| *next_listing <= now)
+                        .unwrap_or(true)
+                })
+                .map(|(_, source)| source.clone())
+                .collect();
+            (sources, store.client.clone())
+        };
+        if sources.is_empty() || update_throttled(client.as_ref()).is_some() {
+            thread::sleep(time::Duration::from_millis(POLL_TICK_MILLIS));
+            continue;
+        }
+
+        for source in sources {
+            let key = source.key();
+            let listed =
+                client.query_listing(&source.listing_path(), source.sort.as_str(), source.limit);
+
+            let changed = {
+                let mut store = Store::write();
+                // The source could have been unwatched or changed while we were listing it
+                if store.watch_sources.get(&key) != Some(&source) {
+                    continue;
+                }
+                store.next_listings.insert(
+                    key.clone(),
+                    Instant::now() + time::Duration::from_millis(LISTING_INTERVAL_MILLIS),
+                );
+                match listed {
+                    Ok(listed) => store.update_source_posts(&key, listed),
+                    Err(err) => {
+                        rid::error!(format!("Failed to list posts of {}", key), err.to_string());
+                        false
                     }
                 }
+            };
+            if changed {
+                rid::post(Reply::UpdatedSource(key));
             }
         }
-        rid::post(Reply::UpdatedScores);
-        thread::sleep(time::Duration::from_millis(RESOLUTION_MILLIS));
     });
 }
+
+// -----------------
+// Score Retention
+// -----------------
+fn downsample_scores() {
+    rid::log_debug!("Creating thread to compact scores");
+    thread::spawn(move || loop {
+        let now = SystemTime::now();
+        let (db, retention) = {
+            let store = Store::read();
+            (store.db.clone(), store.retention)
+        };
+
+        // The database is compacted without holding the store lock so that polling isn't
+        // blocked, only compacting the in-memory scores requires the write lock
+        if let Some(db) = db {
+            match db.downsample_scores(&retention, now) {
+                Ok(rows) => rid::log_debug!("Compacted {} scores in Database", rows),
+                Err(err) => rid::error!("Failed to compact scores", err.to_string()),
+            }
+        }
+        let compacted = Store::write().downsample_posts(now);
+
+        if compacted {
+            rid::post(Reply::CompactedScores);
+        }
+        thread::sleep(time::Duration::from_secs(retention.interval_secs));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    const POST_URL: &str = "https://www.reddit.com/r/rust/comments/abc/first";
+
+    fn wait_until<F: Fn() -> bool>(condition: F) {
+        let deadline = Instant::now() + time::Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for condition");
+            thread::sleep(time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn watches_post_and_persists_polled_scores() {
+        let stub = RedditStub::start().unwrap();
+        stub.add_post(
+            "/r/rust/comments/abc/first",
+            Page {
+                id: "t3_abc".to_string(),
+                title: "first".to_string(),
+                url: POST_URL.to_string(),
+            },
+            42,
+        );
+
+        let db_path = env::temp_dir().join(format!("reddit_ticker_store_{}.sqlite", process::id()));
+        let _ = fs::remove_file(&db_path);
+        {
+            let mut store = Store::write();
+            store.db = Some(Arc::new(DB::new(db_path.to_str().unwrap()).unwrap()));
+            store.set_reddit_client(Arc::new(UreqRedditClient::with_base_url(&stub.base_url())));
+        }
+
+        Store::write().update(1, Msg::StartWatching(POST_URL.to_string()));
+        wait_until(|| Store::read().posts.contains_key("t3_abc"));
+
+        poll_cycle();
+
+        {
+            let store = Store::read();
+            let scores = &store.posts["t3_abc"].scores;
+            assert_eq!(scores.len(), 1);
+            assert_eq!(scores[0].score, 42);
+            assert_eq!(scores[0].front_page_rank, 1);
+
+            let posts = store
+                .db
+                .as_ref()
+                .unwrap()
+                .get_all_posts(ScoresFilter::All)
+                .unwrap();
+            assert_eq!(posts.len(), 1);
+            assert_eq!(posts[0].id, "t3_abc");
+            assert_eq!(posts[0].title, "first");
+            assert_eq!(posts[0].scores.len(), 1);
+            assert_eq!(posts[0].scores[0].score, 42);
+            assert_eq!(posts[0].scores[0].front_page_rank, 1);
+        }
+
+        // The front page isn't listed again until it is stale
+        stub.add_post(
+            "/r/rust/comments/def/second",
+            Page {
+                id: "t3_def".to_string(),
+                title: "second".to_string(),
+                url: "https://www.reddit.com/r/rust/comments/def/second".to_string(),
+            },
+            100,
+        );
+        Store::write().poll_schedules.remove("t3_abc");
+        poll_cycle();
+        assert_eq!(Store::read().posts["t3_abc"].scores[1].front_page_rank, 1);
+
+        {
+            let mut store = Store::write();
+            store.poll_schedules.remove("t3_abc");
+            let front_page = store.front_pages.get_mut("rust").unwrap();
+            front_page.listed_at = Instant::now()
+                .checked_sub(time::Duration::from_millis(LISTING_INTERVAL_MILLIS))
+                .unwrap();
+        }
+        poll_cycle();
+        assert_eq!(Store::read().posts["t3_abc"].scores[2].front_page_rank, 2);
+
+        Store::write().db = None;
+        fs::remove_file(&db_path).unwrap();
+    }
+
+    #[test]
+    fn forgets_excluded_posts_once_they_are_no_longer_listed() {
+        let page = |id: &str| Page {
+            id: id.to_string(),
+            title: id.to_string(),
+            url: format!("https://www.reddit.com/r/rust/comments/{}", id),
+        };
+        let mut store = Store::create();
+        store
+            .excluded_posts
+            .entry("r/rust".to_string())
+            .or_default()
+            .extend(vec!["t3_a".to_string(), "t3_b".to_string()]);
+
+        // Excluded posts that are still listed aren't added
+        assert!(store.update_source_posts("r/rust", vec![page("t3_a"), page("t3_c")]));
+        assert!(!store.posts.contains_key("t3_a"));
+        assert!(store.posts.contains_key("t3_c"));
+        let excluded: Vec<&String> = store.excluded_posts["r/rust"].iter().collect();
+        assert_eq!(excluded, vec!["t3_a"]);
+
+        // Once gone from the listing a post is added again when it shows up later
+        store.update_source_posts("r/rust", vec![page("t3_c")]);
+        assert!(store.excluded_posts["r/rust"].is_empty());
+        store.update_source_posts("r/rust", vec![page("t3_a"), page("t3_c")]);
+        assert!(store.posts.contains_key("t3_a"));
+    }
+}
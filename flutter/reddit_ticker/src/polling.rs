@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+pub const MIN_POLL_INTERVAL_MILLIS: u64 = 1_000;
+pub const MAX_POLL_INTERVAL_MILLIS: u64 = 5 * 60 * 1_000;
+/// Longest time the polling thread sleeps so that new posts and changed intervals are picked up
+pub const POLL_TICK_MILLIS: u64 = 1_000;
+
+// Consecutive polls without a score change after which adaptive polling backs off
+const UNCHANGED_POLLS_BEFORE_BACKOFF: u32 = 3;
+// Score change between two polls from which on a post is considered fast moving
+const FAST_MOVING_SCORE_DELTA: i32 = 10;
+
+// -----------------
+// Poll Schedule
+// -----------------
+/// Tracks when a post is polled next and at which interval.
+#[derive(Debug, Clone)]
+pub struct PollSchedule {
+    /// Set for posts polled at a fixed interval regardless of the Store's interval
+    pub override_millis: Option<u64>,
+    pub interval_millis: u64,
+    pub next_poll: Instant,
+    unchanged_polls: u32,
+}
+
+impl PollSchedule {
+    /// Schedules a post that is polled right away.
+    pub fn new(interval_millis: u64) -> Self {
+        Self {
+            override_millis: None,
+            interval_millis,
+            next_poll: Instant::now(),
+            unchanged_polls: 0,
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next_poll <= now
+    }
+
+    /// Schedules the next poll after the post was polled at `now`.
+    ///
+    /// In `adaptive` mode the interval is doubled for posts whose score didn't change for a
+    /// while and halved for fast moving ones, staying within [MIN_POLL_INTERVAL_MILLIS] and
+    /// [MAX_POLL_INTERVAL_MILLIS]. `score_delta` is `None` if the post had no previous score or
+    /// its score couldn't be retrieved.
+    pub fn polled(
+        &mut self,
+        now: Instant,
+        score_delta: Option<i32>,
+        interval_millis: u64,
+        adaptive: bool,
+    ) {
+        self.interval_millis = match (self.override_millis, score_delta) {
+            (Some(override_millis), _) => override_millis,
+            (None, Some(delta)) if adaptive => self.adapt(delta, interval_millis),
+            (None, _) => interval_millis,
+        };
+        self.next_poll = now + Duration::from_millis(self.interval_millis);
+    }
+
+    /// Applies a changed interval, polling earlier if the new interval is shorter.
+    pub fn reschedule(&mut self, now: Instant, interval_millis: u64) {
+        let interval_millis = self.override_millis.unwrap_or(interval_millis);
+        self.interval_millis = interval_millis;
+        self.unchanged_polls = 0;
+        self.next_poll = self
+            .next_poll
+            .min(now + Duration::from_millis(interval_millis));
+    }
+
+    fn adapt(&mut self, score_delta: i32, interval_millis: u64) -> u64 {
+        if score_delta == 0 {
+            self.unchanged_polls += 1;
+            if self.unchanged_polls < UNCHANGED_POLLS_BEFORE_BACKOFF {
+                return self.interval_millis;
+            }
+            self.unchanged_polls = 0;
+            return (self.interval_millis * 2).min(MAX_POLL_INTERVAL_MILLIS);
+        }
+
+        self.unchanged_polls = 0;
+        if score_delta.abs() >= FAST_MOVING_SCORE_DELTA {
+            (self.interval_millis / 2).max(MIN_POLL_INTERVAL_MILLIS)
+        } else {
+            interval_millis
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Polls the post once per delta in adaptive mode and returns the resulting intervals.
+    fn poll(schedule: &mut PollSchedule, deltas: &[i32], interval_millis: u64) -> Vec<u64> {
+        let now = Instant::now();
+        deltas
+            .iter()
+            .map(|delta| {
+                schedule.polled(now, Some(*delta), interval_millis, true);
+                schedule.interval_millis
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speeds_up_fast_moving_posts() {
+        let mut schedule = PollSchedule::new(8_000);
+        let intervals = poll(&mut schedule, &[15, -10, 20], 8_000);
+        assert_eq!(intervals, vec![4_000, 2_000, 1_000]);
+    }
+
+    #[test]
+    fn returns_to_interval_once_post_slows_down() {
+        let mut schedule = PollSchedule::new(8_000);
+        let intervals = poll(&mut schedule, &[15, 3], 8_000);
+        assert_eq!(intervals, vec![4_000, 8_000]);
+    }
+
+    #[test]
+    fn backs_off_after_unchanged_polls() {
+        let mut schedule = PollSchedule::new(8_000);
+        let intervals = poll(&mut schedule, &[0, 0, 0, 0, 0, 0], 8_000);
+        assert_eq!(
+            intervals,
+            vec![8_000, 8_000, 16_000, 16_000, 16_000, 32_000]
+        );
+    }
+
+    #[test]
+    fn score_change_resets_unchanged_polls() {
+        let mut schedule = PollSchedule::new(8_000);
+        let intervals = poll(&mut schedule, &[0, 0, 1, 0, 0, 0], 8_000);
+        assert_eq!(intervals, vec![8_000, 8_000, 8_000, 8_000, 8_000, 16_000]);
+    }
+
+    #[test]
+    fn clamps_adapted_intervals() {
+        let mut schedule = PollSchedule::new(1_500);
+        let intervals = poll(&mut schedule, &[50, 50], 1_500);
+        assert_eq!(intervals, vec![MIN_POLL_INTERVAL_MILLIS; 2]);
+
+        let mut schedule = PollSchedule::new(MAX_POLL_INTERVAL_MILLIS - 1_000);
+        let intervals = poll(&mut schedule, &[0; 6], MAX_POLL_INTERVAL_MILLIS - 1_000);
+        assert_eq!(intervals[2..], [MAX_POLL_INTERVAL_MILLIS; 4]);
+    }
+
+    #[test]
+    fn only_adapts_in_adaptive_mode_with_known_delta() {
+        let now = Instant::now();
+        let mut schedule = PollSchedule::new(8_000);
+        schedule.polled(now, Some(50), 8_000, false);
+        assert_eq!(schedule.interval_millis, 8_000);
+        schedule.polled(now, None, 8_000, true);
+        assert_eq!(schedule.interval_millis, 8_000);
+        assert_eq!(schedule.next_poll, now + Duration::from_millis(8_000));
+    }
+
+    #[test]
+    fn override_takes_precedence() {
+        let now = Instant::now();
+        let mut schedule = PollSchedule::new(8_000);
+        schedule.override_millis = Some(30_000);
+        schedule.polled(now, Some(50), 8_000, true);
+        assert_eq!(schedule.interval_millis, 30_000);
+
+        schedule.reschedule(now, 2_000);
+        assert_eq!(schedule.interval_millis, 30_000);
+    }
+
+    #[test]
+    fn reschedule_only_polls_earlier() {
+        let now = Instant::now();
+        let mut schedule = PollSchedule::new(8_000);
+        schedule.polled(now, None, 8_000, true);
+
+        schedule.reschedule(now, 2_000);
+        assert_eq!(schedule.next_poll, now + Duration::from_millis(2_000));
+        schedule.reschedule(now, 60_000);
+        assert_eq!(schedule.interval_millis, 60_000);
+        assert_eq!(schedule.next_poll, now + Duration::from_millis(2_000));
+    }
+}
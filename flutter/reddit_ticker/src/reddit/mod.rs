@@ -1,14 +1,25 @@
-#![allow(unused_variables, dead_code)]
+mod normalize;
+mod rate_limit;
 mod reddit;
 mod reddit_api_response;
+mod reddit_listing_response;
 mod reddit_page_response;
-use std::time::SystemTime;
+#[cfg(any(test, feature = "stub"))]
+mod reddit_stub;
+use std::time::{Duration, Instant, SystemTime};
 
+pub use normalize::*;
+pub use rate_limit::*;
 pub use reddit::*;
 pub use reddit_api_response::*;
+pub use reddit_listing_response::*;
 pub use reddit_page_response::*;
+#[cfg(any(test, feature = "stub"))]
+pub use reddit_stub::*;
 
+/// Default millis between two polls of a post
 pub const RESOLUTION_MILLIS: u64 = 5_000;
+pub const NOT_ON_FRONT_PAGE: u32 = 0;
 
 // -----------------
 // Reddit Page
@@ -20,14 +31,57 @@ pub struct Page {
     pub url: String,
 }
 
+// -----------------
+// Reddit Front Page
+// -----------------
+/// Fullnames of the posts on the front page of a subreddit in the order they are ranked.
+#[derive(Debug, Clone)]
+pub struct FrontPage {
+    pub ids: Vec<String>,
+    pub listed_at: Instant,
+}
+
+impl FrontPage {
+    /// Returns `true` once the front page was listed at least `max_age` ago.
+    pub fn is_stale(&self, now: Instant, max_age: Duration) -> bool {
+        now.saturating_duration_since(self.listed_at) >= max_age
+    }
+
+    /// Returns the 1-based rank of the post on the front page or [NOT_ON_FRONT_PAGE].
+    pub fn rank(&self, id: &str) -> u32 {
+        front_page_rank(&self.ids, id)
+    }
+}
+
+// -----------------
+// Reddit Post Info
+// -----------------
+/// Metrics of a post as reported by reddit at the time it was queried.
+#[derive(Debug, Clone)]
+pub struct PostInfo {
+    pub subreddit: String,
+    pub score: i32,
+    pub num_comments: u32,
+    pub upvote_ratio: f64,
+}
+
 // -----------------
 // Reddit Score
 // -----------------
 #[rid::model]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Score {
     pub secs_since_post_added: u64,
+    /// The last score taken within the time covered by this score
     pub score: i32,
+    pub min_score: i32,
+    pub max_score: i32,
+    pub num_comments: u32,
+    pub upvote_ratio: f64,
+    /// 1-based rank of the post on its subreddit's front page or [NOT_ON_FRONT_PAGE]
+    pub front_page_rank: u32,
+    /// Secs covered by this score, `0` for scores that weren't compacted
+    pub resolution_secs: u32,
 }
 
 // -----------------
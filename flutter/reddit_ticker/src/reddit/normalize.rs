@@ -0,0 +1,234 @@
+use std::fmt;
+
+pub const POST_FULLNAME_PREFIX: &str = "t3_";
+const REDDIT_WWW_URL: &str = "https://www.reddit.com";
+// Ids are base36 encoded u64s
+const MAX_ID_LEN: usize = 13;
+
+// -----------------
+// Url Error
+// -----------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+    Empty,
+    /// The url points to a host other than reddit
+    NotReddit(String),
+    /// The url points to reddit, but not to a post or a comment of a post
+    NotAPost(String),
+    InvalidId(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::Empty => write!(f, "No url or post id provided"),
+            UrlError::NotReddit(host) => write!(f, "'{}' is not a reddit url", host),
+            UrlError::NotAPost(url) => write!(f, "'{}' does not link to a reddit post", url),
+            UrlError::InvalidId(id) => write!(f, "'{}' is not a valid reddit post id", id),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+// -----------------
+// Normalizing Post Urls
+// -----------------
+/// Resolves the post the `input` refers to without querying reddit and returns its fullname,
+/// i.e. `t3_abc123`.
+///
+/// Accepts bare ids with or without the `t3_` prefix, `redd.it` short links and urls of any
+/// `reddit.com` host (`www`, `old`, `new`, `np`, mobile) linking to a post, one of its comments
+/// or its gallery.
+pub fn post_fullname(input: &str) -> Result<String, UrlError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(UrlError::Empty);
+    }
+
+    let without_scheme = match input.find("://") {
+        Some(idx) => &input[idx + 3..],
+        None if input.contains('/') || input.contains('.') => input,
+        None => {
+            let id = input.strip_prefix(POST_FULLNAME_PREFIX).unwrap_or(input);
+            return fullname_from_id(id);
+        }
+    };
+
+    // Neither query string nor fragment are needed to identify the post
+    let without_query = without_scheme
+        .split(&['?', '#'][..])
+        .next()
+        .unwrap_or_default();
+    let (host, path) = match without_query.find('/') {
+        Some(idx) => (&without_query[..idx], &without_query[idx..]),
+        None => (without_query, ""),
+    };
+    let host = host.to_lowercase();
+    let segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+
+    if host == "redd.it" || host == "www.redd.it" {
+        return match segments.as_slice() {
+            [id] => fullname_from_id(id),
+            _ => Err(UrlError::NotAPost(input.to_string())),
+        };
+    }
+    if host != "reddit.com" && !host.ends_with(".reddit.com") {
+        return Err(UrlError::NotReddit(host));
+    }
+
+    // /r/<sub>/comments/<id>/<title>/<comment id>, /user/<name>/comments/<id>,
+    // /comments/<id> and /gallery/<id>
+    match segments
+        .iter()
+        .position(|x| *x == "comments" || *x == "gallery")
+        .and_then(|idx| segments.get(idx + 1))
+    {
+        Some(id) => fullname_from_id(id),
+        None => Err(UrlError::NotAPost(input.to_string())),
+    }
+}
+
+/// Url of the post with the given `fullname` which works for all kinds of posts.
+pub fn post_url(fullname: &str) -> String {
+    let id = fullname
+        .strip_prefix(POST_FULLNAME_PREFIX)
+        .unwrap_or(fullname);
+    format!("{}/comments/{}", REDDIT_WWW_URL, id)
+}
+
+fn fullname_from_id(id: &str) -> Result<String, UrlError> {
+    let is_base36 = id
+        .chars()
+        .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase());
+    if id.is_empty() || id.len() > MAX_ID_LEN || !is_base36 {
+        return Err(UrlError::InvalidId(id.to_string()));
+    }
+    Ok(format!("{}{}", POST_FULLNAME_PREFIX, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_post_fullnames() {
+        let cases = [
+            ("abc123", "t3_abc123"),
+            ("t3_abc123", "t3_abc123"),
+            ("  abc123\n", "t3_abc123"),
+            ("https://redd.it/abc123", "t3_abc123"),
+            ("http://redd.it/abc123/", "t3_abc123"),
+            ("redd.it/abc123", "t3_abc123"),
+            (
+                "https://www.reddit.com/r/rust/comments/abc123/some_title/",
+                "t3_abc123",
+            ),
+            (
+                "https://www.reddit.com/r/rust/comments/abc123/some_title/?utm_source=share",
+                "t3_abc123",
+            ),
+            (
+                "https://old.reddit.com/r/rust/comments/abc123/some_title/",
+                "t3_abc123",
+            ),
+            ("https://np.reddit.com/r/rust/comments/abc123", "t3_abc123"),
+            ("https://new.reddit.com/r/rust/comments/abc123", "t3_abc123"),
+            (
+                "https://m.reddit.com/r/rust/comments/abc123/some_title",
+                "t3_abc123",
+            ),
+            (
+                "https://i.reddit.com/r/rust/comments/abc123/some_title",
+                "t3_abc123",
+            ),
+            (
+                "https://WWW.Reddit.com/r/rust/comments/abc123/some_title#comments",
+                "t3_abc123",
+            ),
+            (
+                "https://www.reddit.com/r/rust/comments/abc123/some_title/def456/",
+                "t3_abc123",
+            ),
+            (
+                "https://www.reddit.com/r/rust/comments/abc123/some_title/def456/?context=3",
+                "t3_abc123",
+            ),
+            (
+                "https://www.reddit.com/user/someone/comments/abc123/some_title/",
+                "t3_abc123",
+            ),
+            ("https://www.reddit.com/comments/abc123", "t3_abc123"),
+            ("https://www.reddit.com/gallery/abc123", "t3_abc123"),
+            ("reddit.com/r/rust/comments/abc123", "t3_abc123"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(
+                post_fullname(input),
+                Ok(expected.to_string()),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let cases = [
+            ("", UrlError::Empty),
+            ("   ", UrlError::Empty),
+            (
+                "https://example.com/r/rust/comments/abc123",
+                UrlError::NotReddit("example.com".to_string()),
+            ),
+            (
+                "https://notreddit.com/r/rust/comments/abc123",
+                UrlError::NotReddit("notreddit.com".to_string()),
+            ),
+            (
+                "https://reddit.com.evil.org/r/rust/comments/abc123",
+                UrlError::NotReddit("reddit.com.evil.org".to_string()),
+            ),
+            (
+                "https://www.reddit.com/r/rust",
+                UrlError::NotAPost("https://www.reddit.com/r/rust".to_string()),
+            ),
+            (
+                "https://www.reddit.com/r/rust/comments/",
+                UrlError::NotAPost("https://www.reddit.com/r/rust/comments/".to_string()),
+            ),
+            (
+                "https://redd.it/",
+                UrlError::NotAPost("https://redd.it/".to_string()),
+            ),
+            ("t1_abc123", UrlError::InvalidId("t1_abc123".to_string())),
+            ("abc-123", UrlError::InvalidId("abc-123".to_string())),
+            ("ABC123", UrlError::InvalidId("ABC123".to_string())),
+            (
+                "abcdefghijklmn",
+                UrlError::InvalidId("abcdefghijklmn".to_string()),
+            ),
+            (
+                "https://www.reddit.com/r/rust/comments/abc%20123",
+                UrlError::InvalidId("abc%20123".to_string()),
+            ),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(
+                post_fullname(input).as_ref(),
+                Err(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn builds_post_urls() {
+        assert_eq!(
+            post_url("t3_abc123"),
+            "https://www.reddit.com/comments/abc123"
+        );
+        assert_eq!(post_url("abc123"), "https://www.reddit.com/comments/abc123");
+    }
+}
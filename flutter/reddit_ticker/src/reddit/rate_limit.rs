@@ -0,0 +1,235 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+// Reddit allows 60 requests per minute for clients that don't authenticate via OAuth
+pub const REQUESTS_PER_MINUTE: u32 = 60;
+pub const REQUESTS_BURST: u32 = 10;
+/// Requests that would have to wait longer than this for the rate limit fail instead
+pub const MAX_THROTTLE_WAIT: Duration = Duration::from_secs(10);
+
+const BASE_BACKOFF_MILLIS: u64 = 500;
+const MAX_BACKOFF_MILLIS: u64 = 30_000;
+
+// -----------------
+// Rate Limiter
+// -----------------
+/// Token bucket limiting the requests sent to reddit across all threads.
+///
+/// The bucket holds up to `burst` tokens and is refilled at `requests_per_minute`. Additionally
+/// all requests are held back while reddit asked us to back off.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self::starting_at(requests_per_minute, burst, Instant::now())
+    }
+
+    fn starting_at(requests_per_minute: u32, burst: u32, now: Instant) -> Self {
+        Self {
+            capacity: burst as f64,
+            refill_per_sec: requests_per_minute as f64 / 60.0,
+            state: Mutex::new(BucketState {
+                tokens: burst as f64,
+                last_refill: now,
+                blocked_until: None,
+            }),
+        }
+    }
+
+    /// Blocks until a request may be sent.
+    /// Fails if that would take longer than [MAX_THROTTLE_WAIT].
+    pub fn acquire(&self) -> Result<()> {
+        while let Some(wait) = self.try_acquire(Instant::now())? {
+            thread::sleep(wait);
+        }
+        Ok(())
+    }
+
+    /// Takes a token if a request may be sent at `now`, otherwise returns how long to wait
+    /// before trying again.
+    /// Fails if that would take longer than [MAX_THROTTLE_WAIT].
+    fn try_acquire(&self, now: Instant) -> Result<Option<Duration>> {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, now);
+
+        let blocked_for = state
+            .blocked_until
+            .map(|until| until.saturating_duration_since(now))
+            .unwrap_or_default();
+        if blocked_for.as_millis() == 0 && state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            return Ok(None);
+        }
+        let refill_for =
+            Duration::from_secs_f64((1.0 - state.tokens).max(0.0) / self.refill_per_sec);
+        let wait = blocked_for.max(refill_for);
+
+        if wait > MAX_THROTTLE_WAIT {
+            return Err(anyhow!(
+                "Throttled, no requests are sent for another {}s",
+                wait.as_secs()
+            ));
+        }
+        Ok(Some(wait))
+    }
+
+    /// Holds back all requests for the given `duration`, i.e. when reddit responded with
+    /// `Retry-After`.
+    pub fn block_for(&self, duration: Duration) {
+        self.block_until(Instant::now() + duration);
+    }
+
+    fn block_until(&self, until: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.blocked_until = Some(state.blocked_until.map_or(until, |x| x.max(until)));
+    }
+
+    /// Returns how much longer requests are held back if reddit asked us to back off.
+    pub fn throttled_for(&self) -> Option<Duration> {
+        self.throttled_at(Instant::now())
+    }
+
+    fn throttled_at(&self, now: Instant) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state
+            .blocked_until
+            .map(|until| until.saturating_duration_since(now))
+            .filter(|duration| duration.as_millis() > 0)
+    }
+
+    fn refill(&self, state: &mut BucketState, now: Instant) {
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.tokens =
+            (state.tokens + elapsed.as_secs_f64() * self.refill_per_sec).min(self.capacity);
+        state.last_refill = now;
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(REQUESTS_PER_MINUTE, REQUESTS_BURST)
+    }
+}
+
+// -----------------
+// Backoff
+// -----------------
+/// Time to wait before retrying a request for the given 0-based `attempt`.
+/// Doubles with each attempt and is jittered so that retries of different requests spread out.
+pub fn backoff(attempt: u32) -> Duration {
+    let max_millis = BASE_BACKOFF_MILLIS
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_BACKOFF_MILLIS);
+    let half = max_millis / 2;
+    Duration::from_millis(half + random_u64() % (half + 1))
+}
+
+fn random_u64() -> u64 {
+    // Each RandomState is seeded with different keys which is random enough for jitter
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn acquires_burst_right_away() {
+        let start = Instant::now();
+        let limiter = RateLimiter::starting_at(60, 3, start);
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire(start).unwrap(), None);
+        }
+        assert_eq!(limiter.try_acquire(start).unwrap(), Some(millis(1_000)));
+    }
+
+    #[test]
+    fn refills_tokens_over_time() {
+        let start = Instant::now();
+        let limiter = RateLimiter::starting_at(60, 2, start);
+        limiter.try_acquire(start).unwrap();
+        limiter.try_acquire(start).unwrap();
+
+        // Half a token after 500ms
+        let now = start + millis(500);
+        assert_eq!(limiter.try_acquire(now).unwrap(), Some(millis(500)));
+        assert_eq!(limiter.try_acquire(start + millis(1_000)).unwrap(), None);
+
+        // Never refills beyond the burst
+        let now = start + Duration::from_secs(60);
+        assert_eq!(limiter.try_acquire(now).unwrap(), None);
+        assert_eq!(limiter.try_acquire(now).unwrap(), None);
+        assert!(limiter.try_acquire(now).unwrap().is_some());
+    }
+
+    #[test]
+    fn holds_back_requests_while_blocked() {
+        let start = Instant::now();
+        let limiter = RateLimiter::starting_at(60, 10, start);
+        limiter.block_until(start + millis(2_000));
+        // An earlier block doesn't shorten the current one
+        limiter.block_until(start + millis(1_000));
+
+        assert_eq!(limiter.throttled_at(start), Some(millis(2_000)));
+        assert_eq!(limiter.try_acquire(start).unwrap(), Some(millis(2_000)));
+        let now = start + millis(2_000);
+        assert_eq!(limiter.throttled_at(now), None);
+        assert_eq!(limiter.try_acquire(now).unwrap(), None);
+    }
+
+    #[test]
+    fn fails_when_waiting_longer_than_max_throttle_wait() {
+        let start = Instant::now();
+        let limiter = RateLimiter::starting_at(60, 10, start);
+        limiter.block_until(start + MAX_THROTTLE_WAIT);
+        assert_eq!(limiter.try_acquire(start).unwrap(), Some(MAX_THROTTLE_WAIT));
+
+        limiter.block_until(start + MAX_THROTTLE_WAIT + millis(1));
+        assert!(limiter.try_acquire(start).is_err());
+        // No token is taken by failed attempts
+        let now = start + MAX_THROTTLE_WAIT + millis(1);
+        for _ in 0..10 {
+            assert_eq!(limiter.try_acquire(now).unwrap(), None);
+        }
+
+        // Also when the refill alone takes too long
+        let limiter = RateLimiter::starting_at(1, 1, start);
+        limiter.try_acquire(start).unwrap();
+        assert!(limiter.try_acquire(start).is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter_and_max() {
+        for (attempt, max_millis) in &[(0, 500), (1, 1_000), (3, 4_000), (6, 30_000)] {
+            for _ in 0..20 {
+                let wait = backoff(*attempt);
+                assert!(wait >= millis(max_millis / 2), "{:?}", wait);
+                assert!(wait <= millis(*max_millis), "{:?}", wait);
+            }
+        }
+        assert!(backoff(u32::MAX) <= millis(MAX_BACKOFF_MILLIS));
+    }
+}
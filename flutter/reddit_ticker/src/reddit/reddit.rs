@@ -1,67 +1,321 @@
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+
 use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
 
-use crate::reddit::ApiRoot;
+use crate::reddit::{ApiRoot, NOT_ON_FRONT_PAGE};
 
-use super::{Page, PageRoot};
+use super::{backoff, ListingRoot, Page, PageRoot, PostInfo, RateLimiter, RedditPage, POST_KIND};
 
-pub fn query_page(url: &str) -> Result<Page> {
-    // Cut off query string
-    let url = match url.find("?") {
-        Some(idx) => &url[..idx],
-        None => url,
-    };
+pub const REDDIT_API_BASE_URL: &str = "https://api.reddit.com";
+
+// Max number of ids reddit's /api/info accepts per request
+const API_INFO_MAX_IDS: usize = 100;
+// Number of posts reddit shows on the first page of a subreddit
+pub const FRONT_PAGE_SIZE: usize = 25;
+// Retries of requests failing due to rate limits, server or network errors
+const MAX_RETRIES: u32 = 3;
+// Max number of posts reddit returns for a listing
+const LISTING_MAX_LIMIT: u32 = 100;
+// Subreddits can pin up to two stickied posts which are requested in addition to the limit
+const MAX_STICKIED_POSTS: u32 = 2;
+
+// -----------------
+// Reddit Client
+// -----------------
+
+/// Performs the requests to reddit, abstracted in order to run the app against another server
+/// like the [RedditStub](super::RedditStub).
+pub trait RedditClient: Send + Sync {
+    fn query_page(&self, url: &str) -> Result<Page>;
+    /// Returns the info of the posts with the given fullnames keyed by fullname.
+    /// Posts reddit doesn't know about are missing from the result.
+    /// Fails only if none of the infos could be queried.
+    fn query_post_infos(&self, ids: &[String]) -> Result<HashMap<String, PostInfo>>;
+    /// Returns the fullnames of the posts on the front page of the `subreddit` in order.
+    fn query_front_page(&self, subreddit: &str) -> Result<Vec<String>>;
+    /// Returns up to `limit` posts of the listing at `path`, i.e. `/r/rust/new`, in order.
+    /// Posts pinned by moderators are skipped.
+    fn query_listing(&self, path: &str, sort: &str, limit: u32) -> Result<Vec<Page>>;
+    /// Returns how much longer requests are held back if reddit asked us to back off.
+    fn throttled_for(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Queries reddit via [ureq].
+#[derive(Debug, Clone)]
+pub struct UreqRedditClient {
+    /// When set, pages are requested from this server instead of the one the post url points to
+    base_url: Option<String>,
+    api_base_url: String,
+    /// Shared by all clones of this client, i.e. the polling thread and watch requests
+    limiter: Arc<RateLimiter>,
+}
 
-    let url = format!("{}.json", url.trim_end_matches("/"));
-
-    let page_response: PageRoot = ureq::get(&url)
-        .set("User-Agent", "reddit-ticker")
-        .call()?
-        .into_json()?;
-
-    // .data.children[0].data.{title, id}
-    let data = &page_response
-        .first()
-        .ok_or_else(|| anyhow!("Page response did not contain any pages"))?
-        .data
-        .children
-        .first()
-        .ok_or_else(|| anyhow!("The page did not contain any childre"))?
-        .data;
-
-    let id = data.name.clone();
-
-    let title = data
-        .title
-        .as_ref()
-        .ok_or_else(|| anyhow!("Page was missing a title"))?
-        .clone();
-
-    let url = data
-        .url
-        .as_ref()
-        .ok_or_else(|| anyhow!("Page was missing a url"))?
-        .clone();
-
-    Ok(Page { id, title, url })
+impl UreqRedditClient {
+    pub fn new() -> Self {
+        Self {
+            base_url: None,
+            api_base_url: REDDIT_API_BASE_URL.to_string(),
+            limiter: Arc::new(RateLimiter::default()),
+        }
+    }
+
+    /// Sends all requests to the server at `base_url`, i.e. `http://127.0.0.1:8080`.
+    pub fn with_base_url(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self {
+            base_url: Some(base_url.clone()),
+            api_base_url: base_url,
+            limiter: Arc::new(RateLimiter::default()),
+        }
+    }
+
+    /// Requests the JSON at `url` within the rate limit, retrying when reddit asks us to back
+    /// off or fails with a server or network error.
+    fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            let retrying = attempt < MAX_RETRIES;
+            self.limiter.acquire()?;
+            let err = match ureq::get(url).set("User-Agent", "reddit-ticker").call() {
+                Ok(res) => {
+                    self.update_rate_limit(&res);
+                    return Ok(res.into_json()?);
+                }
+                Err(ureq::Error::Status(status, res)) if status == 429 || status >= 500 => {
+                    self.update_rate_limit(&res);
+                    // The limiter holds back all requests including the retry below
+                    match retry_after(&res) {
+                        Some(retry_after) => self.limiter.block_for(retry_after),
+                        None if !retrying => {}
+                        None if status == 429 => self.limiter.block_for(backoff(attempt)),
+                        None => thread::sleep(backoff(attempt)),
+                    }
+                    anyhow!("Reddit responded with status {}", status)
+                }
+                Err(err @ ureq::Error::Transport(_)) => {
+                    if retrying {
+                        thread::sleep(backoff(attempt));
+                    }
+                    anyhow!(err)
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if !retrying {
+                return Err(anyhow!(
+                    "Failed to request {} after {} retries:\nError: {}",
+                    url,
+                    MAX_RETRIES,
+                    err
+                ));
+            }
+            rid::log_warn!("Retrying request to {}: {}", url, err.to_string());
+            attempt += 1;
+        }
+    }
+
+    /// Holds back requests once we used up the requests reddit allows until the limit resets.
+    fn update_rate_limit(&self, res: &ureq::Response) {
+        let remaining = res
+            .header("X-Ratelimit-Remaining")
+            .and_then(|x| x.trim().parse::<f64>().ok());
+        let reset = res
+            .header("X-Ratelimit-Reset")
+            .and_then(|x| x.trim().parse::<u64>().ok());
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            if remaining < 1.0 {
+                self.limiter.block_for(Duration::from_secs(reset));
+            }
+        }
+    }
+
+    fn page_json_url(&self, url: &str) -> String {
+        // Cut off query string
+        let url = match url.find('?') {
+            Some(idx) => &url[..idx],
+            None => url,
+        };
+        let url = url.trim_end_matches('/');
+
+        match &self.base_url {
+            Some(base_url) => format!("{}{}.json", base_url, url_path(url)),
+            None => format!("{}.json", url),
+        }
+    }
+}
+
+impl Default for UreqRedditClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-const API_INFO_URL: &str = "https://api.reddit.com/api/info";
+impl RedditClient for UreqRedditClient {
+    fn query_page(&self, url: &str) -> Result<Page> {
+        let page_response: PageRoot = self.get_json(&self.page_json_url(url))?;
+
+        // .data.children[0].data.{title, id}
+        let data = &page_response
+            .first()
+            .ok_or_else(|| anyhow!("Page response did not contain any pages"))?
+            .data
+            .children
+            .first()
+            .ok_or_else(|| anyhow!("The page did not contain any childre"))?
+            .data;
 
-pub fn query_score(id: &str) -> Result<i32> {
-    let url = format!("{}?id={}", API_INFO_URL, id);
+        let id = data.name.clone();
 
-    let api_response: ApiRoot = ureq::get(&url)
-        .set("User-Agent", "reddit-ticker")
-        .call()?
-        .into_json()?;
+        let title = data
+            .title
+            .as_ref()
+            .ok_or_else(|| anyhow!("Page was missing a title"))?
+            .clone();
 
-    let score = api_response
-        .data
-        .children
-        .first()
-        .ok_or_else(|| anyhow!("Post info was missing children"))?
-        .data
-        .score;
+        let url = data
+            .url
+            .as_ref()
+            .ok_or_else(|| anyhow!("Page was missing a url"))?
+            .clone();
 
-    Ok(score)
+        Ok(Page { id, title, url })
+    }
+
+    fn query_post_infos(&self, ids: &[String]) -> Result<HashMap<String, PostInfo>> {
+        let mut infos = HashMap::with_capacity(ids.len());
+        let chunks = ids.chunks(API_INFO_MAX_IDS);
+        let num_chunks = chunks.len();
+        let mut failed_chunks = vec![];
+        for chunk in chunks {
+            let url = format!("{}/api/info?id={}", self.api_base_url, chunk.join(","));
+
+            // A failing chunk shouldn't prevent us from updating the scores of the others
+            let api_response: ApiRoot = match self.get_json(&url) {
+                Ok(api_response) => api_response,
+                Err(err) => {
+                    failed_chunks.push((chunk.len(), err));
+                    continue;
+                }
+            };
+
+            for child in api_response.data.children {
+                let data = child.data;
+                let info = PostInfo {
+                    subreddit: data.subreddit,
+                    score: data.score,
+                    num_comments: data.num_comments,
+                    upvote_ratio: data.upvote_ratio,
+                };
+                infos.insert(data.name, info);
+            }
+        }
+
+        // Let the caller report a complete failure, i.e. while reddit is down, only once
+        if num_chunks > 0 && failed_chunks.len() == num_chunks {
+            let (_, err) = failed_chunks.pop().unwrap();
+            return Err(anyhow!(
+                "Failed to query scores of {} posts:\nError: {}",
+                ids.len(),
+                err
+            ));
+        }
+        for (len, err) in failed_chunks {
+            rid::error!(
+                format!("Failed to query scores of {} posts", len),
+                err.to_string()
+            );
+        }
+        Ok(infos)
+    }
+
+    fn query_front_page(&self, subreddit: &str) -> Result<Vec<String>> {
+        let base_url = match &self.base_url {
+            Some(base_url) => base_url.as_str(),
+            None => REDDIT_API_BASE_URL,
+        };
+        let url = format!(
+            "{}/r/{}/hot.json?limit={}",
+            base_url, subreddit, FRONT_PAGE_SIZE
+        );
+
+        let listing: RedditPage = self.get_json(&url)?;
+
+        Ok(listing
+            .data
+            .children
+            .into_iter()
+            .map(|child| child.data.name)
+            .collect())
+    }
+
+    fn query_listing(&self, path: &str, sort: &str, limit: u32) -> Result<Vec<Page>> {
+        let base_url = match &self.base_url {
+            Some(base_url) => base_url.as_str(),
+            None => REDDIT_API_BASE_URL,
+        };
+        // Stickied posts count towards the limit but are filtered out below
+        let url = format!(
+            "{}{}.json?sort={}&limit={}",
+            base_url,
+            path,
+            sort,
+            limit
+                .saturating_add(MAX_STICKIED_POSTS)
+                .min(LISTING_MAX_LIMIT)
+        );
+
+        let listing: ListingRoot = self.get_json(&url)?;
+        Ok(listing
+            .data
+            .children
+            .into_iter()
+            .filter(|child| child.kind == POST_KIND && !child.data.stickied)
+            .filter_map(|child| {
+                let data = child.data;
+                match (data.title, data.url) {
+                    (Some(title), Some(url)) => Some(Page {
+                        id: data.name,
+                        title,
+                        url,
+                    }),
+                    _ => None,
+                }
+            })
+            .take(limit as usize)
+            .collect())
+    }
+
+    fn throttled_for(&self) -> Option<Duration> {
+        self.limiter.throttled_for()
+    }
+}
+
+/// Returns the time reddit asked us to wait via the `Retry-After` header in seconds.
+fn retry_after(res: &ureq::Response) -> Option<Duration> {
+    res.header("Retry-After")
+        .and_then(|x| x.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Returns the 1-based rank of the post on the `front_page` or [NOT_ON_FRONT_PAGE].
+pub fn front_page_rank(front_page: &[String], id: &str) -> u32 {
+    match front_page.iter().position(|x| x == id) {
+        Some(idx) => idx as u32 + 1,
+        None => NOT_ON_FRONT_PAGE,
+    }
+}
+
+/// Returns the path of the `url` including the leading `/`, i.e. `/r/rust/comments/xyz`.
+fn url_path(url: &str) -> &str {
+    let without_scheme = match url.find("://") {
+        Some(idx) => &url[idx + 3..],
+        None => url,
+    };
+    match without_scheme.find('/') {
+        Some(idx) => &without_scheme[idx..],
+        None => "",
+    }
 }
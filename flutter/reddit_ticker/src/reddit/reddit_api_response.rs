@@ -17,5 +17,10 @@ pub struct Children {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Data2 {
+    /// Fullname of the post, i.e. `t3_pjm5k6`
+    pub name: String,
+    pub subreddit: String,
     pub score: i32,
+    pub num_comments: u32,
+    pub upvote_ratio: f64,
 }
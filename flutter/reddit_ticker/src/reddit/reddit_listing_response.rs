@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Kind of listing children that are posts, listings of users may include comments as well
+pub const POST_KIND: &str = "t3";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingRoot {
+    pub data: ListingData,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingData {
+    pub children: Vec<ListingChild>,
+    /// Fullname of the last child used to request the next part of the listing
+    pub after: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingChild {
+    pub kind: String,
+    pub data: ListingPost,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingPost {
+    pub name: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// Pinned by moderators, these stay on top of the listing regardless of score
+    #[serde(default)]
+    pub stickied: bool,
+}
@@ -0,0 +1,468 @@
+use std::{
+    cmp::Reverse,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    reddit_api_response::{self as api, ApiRoot},
+    reddit_listing_response::{self as listing, ListingRoot},
+    reddit_page_response::{self as page, PageRoot, RedditPage},
+    Page, FRONT_PAGE_SIZE, POST_FULLNAME_PREFIX, POST_KIND,
+};
+
+// -----------------
+// Reddit Stub Server
+// -----------------
+
+/// In-process HTTP server serving canned reddit responses so that watching and polling posts
+/// works without access to reddit.
+///
+/// Point the app at it via `Msg::SetRedditBaseUrl` with the [RedditStub::base_url] or by
+/// creating a [UreqRedditClient](super::UreqRedditClient) with that base url. Posts are then
+/// watched via their regular reddit url.
+/// The server shuts down when the stub is dropped.
+pub struct RedditStub {
+    addr: SocketAddr,
+    posts: Arc<Mutex<Vec<StubPost>>>,
+    throttle: Arc<Mutex<Throttle>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Clone)]
+struct StubPost {
+    path: String,
+    subreddit: String,
+    author: String,
+    page: Page,
+    score: i32,
+    num_comments: u32,
+    upvote_ratio: f64,
+}
+
+/// Rate limit responses sent instead of the requested data
+#[derive(Debug, Clone, Default)]
+struct Throttle {
+    responses: usize,
+    retry_after_secs: u64,
+}
+
+impl RedditStub {
+    /// Starts serving on a free port of the loopback interface.
+    pub fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|err| anyhow!("Failed to bind reddit stub:\nError: {}", err))?;
+        let addr = listener.local_addr()?;
+        let posts = Arc::new(Mutex::new(Vec::<StubPost>::new()));
+        let throttle = Arc::new(Mutex::new(Throttle::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let posts = posts.clone();
+            let throttle = throttle.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let res = stream
+                        .map_err(|err| anyhow!(err))
+                        .and_then(|stream| handle_connection(stream, &posts, &throttle));
+                    if let Err(err) = res {
+                        rid::log_warn!("Reddit stub failed to respond: {}", err.to_string());
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            posts,
+            throttle,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Serves the `page` at `path`, i.e. `/r/rust/comments/abc/title`, and its `score` via
+    /// `/api/info`.
+    /// The post is listed on the front page of the subreddit included in the `path` which is
+    /// ordered by score, the `new` listing has the posts added last first.
+    pub fn add_post(&self, path: &str, page: Page, score: i32) {
+        let path = path.trim_end_matches('/').to_string();
+        let subreddit = path
+            .strip_prefix("/r/")
+            .and_then(|x| x.split('/').next())
+            .unwrap_or_default()
+            .to_string();
+        let mut posts = self.posts.lock().unwrap();
+        posts.retain(|x| x.page.id != page.id);
+        posts.push(StubPost {
+            path,
+            subreddit,
+            author: String::new(),
+            page,
+            score,
+            num_comments: 0,
+            upvote_ratio: 1.0,
+        });
+    }
+
+    pub fn set_score(&self, id: &str, score: i32) {
+        let mut posts = self.posts.lock().unwrap();
+        if let Some(post) = posts.iter_mut().find(|x| x.page.id == id) {
+            post.score = score;
+        }
+    }
+
+    pub fn set_metrics(&self, id: &str, num_comments: u32, upvote_ratio: f64) {
+        let mut posts = self.posts.lock().unwrap();
+        if let Some(post) = posts.iter_mut().find(|x| x.page.id == id) {
+            post.num_comments = num_comments;
+            post.upvote_ratio = upvote_ratio;
+        }
+    }
+
+    /// Lists the post among the posts submitted by the user with the given name.
+    pub fn set_author(&self, id: &str, author: &str) {
+        let mut posts = self.posts.lock().unwrap();
+        if let Some(post) = posts.iter_mut().find(|x| x.page.id == id) {
+            post.author = author.to_string();
+        }
+    }
+
+    pub fn remove_post(&self, id: &str) {
+        self.posts.lock().unwrap().retain(|x| x.page.id != id);
+    }
+
+    /// Responds to the next `responses` requests with `429 Too Many Requests` asking to retry
+    /// after `retry_after_secs`.
+    pub fn throttle(&self, responses: usize, retry_after_secs: u64) {
+        *self.throttle.lock().unwrap() = Throttle {
+            responses,
+            retry_after_secs,
+        };
+    }
+}
+
+impl Drop for RedditStub {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Unblock the listener which is waiting for the next connection
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// -----------------
+// Handling Requests
+// -----------------
+fn handle_connection(
+    mut stream: TcpStream,
+    posts: &Mutex<Vec<StubPost>>,
+    throttle: &Mutex<Throttle>,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Headers aren't needed to respond, but need to be consumed before answering
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+    }
+
+    {
+        let mut throttle = throttle.lock().unwrap();
+        if throttle.responses > 0 {
+            throttle.responses -= 1;
+            write!(
+                stream,
+                "HTTP/1.1 429 Too Many Requests\r\nRetry-After: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                throttle.retry_after_secs
+            )?;
+            stream.flush()?;
+            return Ok(());
+        }
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let body = respond(target, &posts.lock().unwrap())?;
+    let status = match body {
+        Some(_) => "200 OK",
+        None => "404 Not Found",
+    };
+    let body = body.unwrap_or_default();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Returns the JSON body for the requested `target` or `None` if nothing is served there.
+fn respond(target: &str, posts: &[StubPost]) -> Result<Option<String>> {
+    let (path, query) = match target.find('?') {
+        Some(idx) => (&target[..idx], &target[idx + 1..]),
+        None => (target, ""),
+    };
+
+    if path == "/api/info" {
+        let ids: Vec<&str> = query
+            .split('&')
+            .filter_map(|param| param.strip_prefix("id="))
+            .flat_map(|ids| ids.split(','))
+            .collect();
+        let children = posts
+            .iter()
+            .filter(|x| ids.contains(&x.page.id.as_str()))
+            .map(|x| api::Children {
+                data: api::Data2 {
+                    name: x.page.id.clone(),
+                    subreddit: x.subreddit.clone(),
+                    score: x.score,
+                    num_comments: x.num_comments,
+                    upvote_ratio: x.upvote_ratio,
+                },
+            })
+            .collect();
+        let root = ApiRoot {
+            data: api::Data { children },
+        };
+        return Ok(Some(serde_json::to_string(&root)?));
+    }
+
+    let path = match path.strip_suffix(".json") {
+        Some(path) => path.trim_end_matches('/'),
+        None => return Ok(None),
+    };
+
+    let limit = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("limit="))
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(FRONT_PAGE_SIZE);
+
+    // /r/<subreddit>/<sort> and /user/<name>/submitted
+    let listed: Option<Vec<&StubPost>> = match path.strip_prefix("/r/").map(|x| x.split('/')) {
+        Some(mut segments) => match (segments.next(), segments.next(), segments.next()) {
+            (Some(subreddit), Some(sort), None) => {
+                let mut listed: Vec<&StubPost> =
+                    posts.iter().filter(|x| x.subreddit == subreddit).collect();
+                match sort {
+                    "new" => listed.reverse(),
+                    _ => listed.sort_by_key(|x| Reverse(x.score)),
+                }
+                Some(listed)
+            }
+            _ => None,
+        },
+        None => path
+            .strip_prefix("/user/")
+            .and_then(|x| x.strip_suffix("/submitted"))
+            .map(|author| posts.iter().rev().filter(|x| x.author == author).collect()),
+    };
+    if let Some(listed) = listed {
+        let children = listed
+            .into_iter()
+            .take(limit)
+            .map(|x| listing::ListingChild {
+                kind: POST_KIND.to_string(),
+                data: listing::ListingPost {
+                    name: x.page.id.clone(),
+                    title: Some(x.page.title.clone()),
+                    url: Some(x.page.url.clone()),
+                    stickied: false,
+                },
+            })
+            .collect();
+        let root = ListingRoot {
+            data: listing::ListingData {
+                children,
+                after: None,
+            },
+        };
+        return Ok(Some(serde_json::to_string(&root)?));
+    }
+
+    // Posts are requested via /comments/<id> once their url was normalized
+    let post = match path.strip_prefix("/comments/") {
+        Some(id) => {
+            let fullname = format!("{}{}", POST_FULLNAME_PREFIX, id);
+            posts.iter().find(|x| x.page.id == fullname)
+        }
+        None => posts.iter().find(|x| x.path == path),
+    };
+    match post {
+        Some(post) => {
+            let root: PageRoot = vec![RedditPage {
+                data: page::ChildContainer {
+                    children: vec![page::Children {
+                        data: page::ChildData {
+                            name: post.page.id.clone(),
+                            title: Some(post.page.title.clone()),
+                            url: Some(post.page.url.clone()),
+                        },
+                    }],
+                },
+            }];
+            Ok(Some(serde_json::to_string(&root)?))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::reddit::{RedditClient, UreqRedditClient};
+
+    fn page(id: &str, title: &str) -> Page {
+        Page {
+            id: format!("{}{}", POST_FULLNAME_PREFIX, id),
+            title: title.to_string(),
+            url: format!("https://www.reddit.com/r/rust/comments/{}/{}", id, title),
+        }
+    }
+
+    fn stub_with_posts() -> RedditStub {
+        let stub = RedditStub::start().unwrap();
+        stub.add_post("/r/rust/comments/abc/first", page("abc", "first"), 10);
+        stub.add_post("/r/rust/comments/def/second", page("def", "second"), 20);
+        stub.add_post("/r/flutter/comments/ghi/third", page("ghi", "third"), 30);
+        stub
+    }
+
+    #[test]
+    fn queries_pages() {
+        let stub = stub_with_posts();
+        let client = UreqRedditClient::with_base_url(&stub.base_url());
+
+        let page = client
+            .query_page("https://www.reddit.com/r/rust/comments/abc/first/?utm_source=share")
+            .unwrap();
+        assert_eq!(page.id, "t3_abc");
+        assert_eq!(page.title, "first");
+
+        let page = client
+            .query_page("https://www.reddit.com/comments/def")
+            .unwrap();
+        assert_eq!(page.id, "t3_def");
+
+        assert!(client
+            .query_page("https://www.reddit.com/r/rust/comments/xyz/missing")
+            .is_err());
+    }
+
+    #[test]
+    fn queries_post_infos() {
+        let stub = stub_with_posts();
+        let client = UreqRedditClient::with_base_url(&stub.base_url());
+        stub.set_score("t3_abc", 42);
+        stub.set_metrics("t3_abc", 7, 0.5);
+
+        let ids = vec![
+            "t3_abc".to_string(),
+            "t3_ghi".to_string(),
+            "t3_xyz".to_string(),
+        ];
+        let infos = client.query_post_infos(&ids).unwrap();
+        assert_eq!(infos.len(), 2);
+
+        let info = &infos["t3_abc"];
+        assert_eq!(info.subreddit, "rust");
+        assert_eq!(info.score, 42);
+        assert_eq!(info.num_comments, 7);
+        assert!((info.upvote_ratio - 0.5).abs() < f64::EPSILON);
+        assert_eq!(infos["t3_ghi"].score, 30);
+    }
+
+    #[test]
+    fn queries_listings() {
+        let stub = stub_with_posts();
+        let client = UreqRedditClient::with_base_url(&stub.base_url());
+        stub.set_author("t3_abc", "someone");
+
+        let ids = |pages: Vec<Page>| pages.into_iter().map(|x| x.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(client.query_listing("/r/rust/hot", "hot", 10).unwrap()),
+            vec!["t3_def", "t3_abc"]
+        );
+        assert_eq!(
+            ids(client.query_listing("/r/rust/new", "new", 1).unwrap()),
+            vec!["t3_def"]
+        );
+        assert_eq!(
+            ids(client
+                .query_listing("/user/someone/submitted", "new", 10)
+                .unwrap()),
+            vec!["t3_abc"]
+        );
+        assert_eq!(
+            client.query_front_page("rust").unwrap(),
+            vec!["t3_def", "t3_abc"]
+        );
+
+        stub.remove_post("t3_def");
+        assert_eq!(
+            ids(client.query_listing("/r/rust/hot", "hot", 10).unwrap()),
+            vec!["t3_abc"]
+        );
+    }
+
+    #[test]
+    fn retries_when_asked_to_back_off() {
+        let stub = stub_with_posts();
+        let client = UreqRedditClient::with_base_url(&stub.base_url());
+
+        stub.throttle(2, 0);
+        let page = client
+            .query_page("https://www.reddit.com/comments/abc")
+            .unwrap();
+        assert_eq!(page.id, "t3_abc");
+        assert_eq!(client.throttled_for(), None);
+    }
+
+    #[test]
+    fn fails_fast_while_throttled() {
+        let stub = stub_with_posts();
+        let client = UreqRedditClient::with_base_url(&stub.base_url());
+
+        // Waiting a minute exceeds MAX_THROTTLE_WAIT, so the retry fails without being sent
+        stub.throttle(1, 60);
+        assert!(client
+            .query_page("https://www.reddit.com/comments/abc")
+            .is_err());
+        let throttled_for = client.throttled_for().unwrap();
+        assert!(throttled_for > Duration::from_secs(50));
+
+        // Other requests share the rate limiter and are held back as well
+        assert!(client
+            .query_page("https://www.reddit.com/comments/def")
+            .is_err());
+        assert!(client.query_post_infos(&["t3_abc".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::reddit::Score;
+
+pub const MINUTE_SECS: u32 = 60;
+pub const HOUR_SECS: u32 = 60 * 60;
+
+pub const RAW_SCORES_RETENTION_SECS: u64 = 60 * 60;
+pub const MINUTE_SCORES_RETENTION_SECS: u64 = 24 * 60 * 60;
+pub const RETENTION_INTERVAL_SECS: u64 = 10 * 60;
+
+// -----------------
+// Retention Policy
+// -----------------
+/// Decides for how long scores are kept at which resolution.
+///
+/// Scores are polled every [RESOLUTION_MILLIS](crate::reddit::RESOLUTION_MILLIS) by default which
+/// adds up quickly, therefore older scores are compacted into per minute and later per hour buckets.
+/// Each bucket keeps the min and max score of the scores it replaced and the metrics of the
+/// last one.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Scores older than this are compacted into per minute buckets
+    pub raw_secs: u64,
+    /// Per minute buckets older than this are compacted into per hour buckets
+    pub minute_secs: u64,
+    /// Time between compaction runs
+    pub interval_secs: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_secs: RAW_SCORES_RETENTION_SECS,
+            minute_secs: MINUTE_SCORES_RETENTION_SECS,
+            interval_secs: RETENTION_INTERVAL_SECS,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Compacts the `scores` of a post that was added `secs_since_post_added` ago.
+    /// The `scores` need to be ordered by the time they were taken.
+    ///
+    /// Returns `true` if any scores were compacted.
+    pub fn downsample(&self, scores: &mut Vec<Score>, secs_since_post_added: u64) -> bool {
+        let minutes = compact(
+            scores,
+            self.minute_cutoff_secs(secs_since_post_added),
+            MINUTE_SECS,
+        );
+        let hours = compact(
+            scores,
+            self.hour_cutoff_secs(secs_since_post_added),
+            HOUR_SECS,
+        );
+        minutes || hours
+    }
+
+    /// Raw scores taken before the returned secs since the post was added are compacted into
+    /// per minute buckets by [RetentionPolicy::downsample].
+    pub fn minute_cutoff_secs(&self, secs_since_post_added: u64) -> u64 {
+        self.cutoff_secs(secs_since_post_added, self.raw_secs, MINUTE_SECS)
+    }
+
+    /// Scores finer than an hour taken before the returned secs since the post was added are
+    /// compacted into per hour buckets by [RetentionPolicy::downsample].
+    pub fn hour_cutoff_secs(&self, secs_since_post_added: u64) -> u64 {
+        self.cutoff_secs(secs_since_post_added, self.minute_secs, HOUR_SECS)
+    }
+
+    fn cutoff_secs(
+        &self,
+        secs_since_post_added: u64,
+        retention_secs: u64,
+        bucket_secs: u32,
+    ) -> u64 {
+        // Only compact full buckets, otherwise the remainder of a bucket would end up in a
+        // separate bucket on the next run
+        let bucket_secs = bucket_secs as u64;
+        secs_since_post_added.saturating_sub(retention_secs) / bucket_secs * bucket_secs
+    }
+}
+
+// -----------------
+// Compacting Scores
+// -----------------
+/// Merges all scores finer than `bucket_secs` that were taken before `cutoff_secs` into buckets
+/// of `bucket_secs`.
+fn compact(scores: &mut Vec<Score>, cutoff_secs: u64, bucket_secs: u32) -> bool {
+    let needs_compacting = |score: &Score| {
+        score.secs_since_post_added < cutoff_secs && score.resolution_secs < bucket_secs
+    };
+    if !scores.iter().any(needs_compacting) {
+        return false;
+    }
+
+    let mut compacted: Vec<Score> = Vec::with_capacity(scores.len());
+    for score in scores.drain(..) {
+        if !needs_compacting(&score) {
+            compacted.push(score);
+            continue;
+        }
+
+        let bucket_start = score.secs_since_post_added / bucket_secs as u64 * bucket_secs as u64;
+        match compacted.last_mut() {
+            Some(bucket)
+                if bucket.resolution_secs == bucket_secs
+                    && bucket.secs_since_post_added == bucket_start =>
+            {
+                bucket.score = score.score;
+                bucket.min_score = bucket.min_score.min(score.min_score);
+                bucket.max_score = bucket.max_score.max(score.max_score);
+                bucket.num_comments = score.num_comments;
+                bucket.upvote_ratio = score.upvote_ratio;
+                bucket.front_page_rank = score.front_page_rank;
+            }
+            _ => compacted.push(Score {
+                secs_since_post_added: bucket_start,
+                resolution_secs: bucket_secs,
+                ..score
+            }),
+        }
+    }
+    *scores = compacted;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(raw_secs: u64, minute_secs: u64) -> RetentionPolicy {
+        RetentionPolicy {
+            raw_secs,
+            minute_secs,
+            interval_secs: RETENTION_INTERVAL_SECS,
+        }
+    }
+
+    /// Score taken at the given secs, its comment count is the secs as well to tell which score
+    /// the metrics of a bucket were taken from.
+    fn score(secs_since_post_added: u64, score: i32) -> Score {
+        Score {
+            secs_since_post_added,
+            score,
+            min_score: score,
+            max_score: score,
+            num_comments: secs_since_post_added as u32,
+            upvote_ratio: 1.0,
+            front_page_rank: 1,
+            resolution_secs: 0,
+        }
+    }
+
+    /// (secs_since_post_added, resolution_secs, score, min_score, max_score, num_comments)
+    fn summary(scores: &[Score]) -> Vec<(u64, u32, i32, i32, i32, u32)> {
+        scores
+            .iter()
+            .map(|x| {
+                (
+                    x.secs_since_post_added,
+                    x.resolution_secs,
+                    x.score,
+                    x.min_score,
+                    x.max_score,
+                    x.num_comments,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_recent_scores() {
+        let mut scores = vec![score(0, 1), score(5, 2), score(10, 3)];
+        assert!(!RetentionPolicy::default().downsample(&mut scores, 1000));
+        assert_eq!(
+            summary(&scores),
+            vec![(0, 0, 1, 1, 1, 0), (5, 0, 2, 2, 2, 5), (10, 0, 3, 3, 3, 10)]
+        );
+    }
+
+    #[test]
+    fn compacts_raw_scores_into_minute_buckets() {
+        let mut scores = vec![
+            score(0, 5),
+            score(30, 2),
+            score(59, 3),
+            score(60, 10),
+            score(90, 8),
+            score(119, 9),
+            score(120, 20),
+            score(150, 21),
+        ];
+        // Scores before 120s are compacted, the bucket from 120s on isn't complete yet
+        assert!(policy(60, 3600).downsample(&mut scores, 185));
+        assert_eq!(
+            summary(&scores),
+            vec![
+                (0, MINUTE_SECS, 3, 2, 5, 59),
+                (60, MINUTE_SECS, 9, 8, 10, 119),
+                (120, 0, 20, 20, 20, 120),
+                (150, 0, 21, 21, 21, 150),
+            ]
+        );
+    }
+
+    #[test]
+    fn compacts_minute_buckets_into_hour_buckets() {
+        let mut scores: Vec<Score> = (0..=12)
+            .map(|idx| score(idx * 600, (idx as i32 % 4) * 10))
+            .collect();
+        scores.push(score(7250, 100));
+
+        // Minute buckets end at 7200s, hour buckets at 3600s
+        assert!(policy(60, 3600).downsample(&mut scores, 7300));
+        assert_eq!(
+            summary(&scores),
+            vec![
+                (0, HOUR_SECS, 10, 0, 30, 3000),
+                (3600, MINUTE_SECS, 20, 20, 20, 3600),
+                (4200, MINUTE_SECS, 30, 30, 30, 4200),
+                (4800, MINUTE_SECS, 0, 0, 0, 4800),
+                (5400, MINUTE_SECS, 10, 10, 10, 5400),
+                (6000, MINUTE_SECS, 20, 20, 20, 6000),
+                (6600, MINUTE_SECS, 30, 30, 30, 6600),
+                (7200, 0, 0, 0, 0, 7200),
+                (7250, 0, 100, 100, 100, 7250),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_min_and_max_when_compacting_buckets_again() {
+        let mut scores = vec![
+            score(0, 5),
+            score(30, -3),
+            score(60, 50),
+            score(90, 7),
+            score(3600, 8),
+        ];
+        let policy = policy(60, 3600);
+        assert!(policy.downsample(&mut scores, 3700));
+        assert_eq!(
+            summary(&scores),
+            vec![
+                (0, MINUTE_SECS, -3, -3, 5, 30),
+                (60, MINUTE_SECS, 7, 7, 50, 90),
+                (3600, 0, 8, 8, 8, 3600),
+            ]
+        );
+
+        assert!(policy.downsample(&mut scores, 7300));
+        assert_eq!(
+            summary(&scores),
+            vec![
+                (0, HOUR_SECS, 7, -3, 50, 90),
+                (3600, MINUTE_SECS, 8, 8, 8, 3600)
+            ]
+        );
+    }
+
+    #[test]
+    fn compacting_twice_changes_nothing() {
+        let mut scores: Vec<Score> = (0..100).map(|idx| score(idx * 45, idx as i32)).collect();
+        let policy = policy(60, 3600);
+        assert!(policy.downsample(&mut scores, 4600));
+        let compacted = summary(&scores);
+
+        assert!(!policy.downsample(&mut scores, 4600));
+        assert_eq!(summary(&scores), compacted);
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{anyhow, Result};
+
+// Max number of posts reddit returns for a listing
+pub const MAX_SOURCE_LIMIT: u32 = 100;
+pub const USER_SOURCE_LIMIT: u32 = 10;
+pub const LISTING_INTERVAL_MILLIS: u64 = 60_000;
+
+const MAX_NAME_LEN: usize = 21;
+
+// -----------------
+// Listing Sort
+// -----------------
+#[rid::model]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListingSort {
+    Hot,
+    New,
+    Top,
+    Rising,
+}
+
+impl ListingSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingSort::Hot => "hot",
+            ListingSort::New => "new",
+            ListingSort::Top => "top",
+            ListingSort::Rising => "rising",
+        }
+    }
+
+    pub fn parse(sort: &str) -> Option<Self> {
+        match sort {
+            "hot" => Some(ListingSort::Hot),
+            "new" => Some(ListingSort::New),
+            "top" => Some(ListingSort::Top),
+            "rising" => Some(ListingSort::Rising),
+            _ => None,
+        }
+    }
+}
+
+// -----------------
+// Watch Source
+// -----------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    Subreddit,
+    User,
+}
+
+impl SourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceKind::Subreddit => "subreddit",
+            SourceKind::User => "user",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "subreddit" => Some(SourceKind::Subreddit),
+            "user" => Some(SourceKind::User),
+            _ => None,
+        }
+    }
+}
+
+/// A subreddit or user whose top `limit` posts are watched automatically.
+/// Posts are no longer watched once they fall out of the listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchSource {
+    pub kind: SourceKind,
+    pub name: String,
+    pub sort: ListingSort,
+    pub limit: u32,
+}
+
+impl WatchSource {
+    /// Validates the `name` which may include a `r/` prefix.
+    pub fn subreddit(name: &str, sort: ListingSort, limit: u32) -> Result<Self> {
+        if limit == 0 || limit > MAX_SOURCE_LIMIT {
+            return Err(anyhow!(
+                "Limit needs to be between 1 and {}, got {}",
+                MAX_SOURCE_LIMIT,
+                limit
+            ));
+        }
+        Ok(Self {
+            kind: SourceKind::Subreddit,
+            name: valid_name(name, &["/r/", "r/"])?,
+            sort,
+            limit,
+        })
+    }
+
+    /// Watches the posts the user submitted most recently, the `name` may include a `u/` prefix.
+    pub fn user(name: &str) -> Result<Self> {
+        Ok(Self {
+            kind: SourceKind::User,
+            name: valid_name(name, &["/user/", "user/", "/u/", "u/"])?,
+            sort: ListingSort::New,
+            limit: USER_SOURCE_LIMIT,
+        })
+    }
+
+    /// Identifies the source, i.e. `r/rust` or `u/spez`.
+    /// Reddit names are case insensitive, therefore so are keys.
+    pub fn key(&self) -> String {
+        let name = self.name.to_lowercase();
+        match self.kind {
+            SourceKind::Subreddit => format!("r/{}", name),
+            SourceKind::User => format!("u/{}", name),
+        }
+    }
+
+    /// Path of the listing relative to reddit's base url without `.json`.
+    pub fn listing_path(&self) -> String {
+        match self.kind {
+            SourceKind::Subreddit => format!("/r/{}/{}", self.name, self.sort.as_str()),
+            SourceKind::User => format!("/user/{}/submitted", self.name),
+        }
+    }
+}
+
+fn valid_name(name: &str, prefixes: &[&str]) -> Result<String> {
+    let name = name.trim();
+    let name = prefixes
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+        .trim_end_matches('/');
+    let is_valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !is_valid {
+        return Err(anyhow!("'{}' is not a valid subreddit or user name", name));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_ignore_case_and_prefixes() {
+        let rust = WatchSource::subreddit("r/rust", ListingSort::Hot, 10).unwrap();
+        let rust_upper = WatchSource::subreddit("/r/Rust/", ListingSort::New, 5).unwrap();
+        assert_eq!(rust.key(), "r/rust");
+        assert_eq!(rust_upper.key(), rust.key());
+        // The name is kept as entered
+        assert_eq!(rust_upper.listing_path(), "/r/Rust/new");
+
+        let user = WatchSource::user("u/Spez").unwrap();
+        assert_eq!(user.key(), "u/spez");
+    }
+
+    #[test]
+    fn rejects_invalid_sources() {
+        assert!(WatchSource::subreddit("rust", ListingSort::Hot, 0).is_err());
+        assert!(WatchSource::subreddit("rust", ListingSort::Hot, MAX_SOURCE_LIMIT + 1).is_err());
+        assert!(WatchSource::subreddit("r/", ListingSort::Hot, 10).is_err());
+        assert!(WatchSource::user("no spaces").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 import 'dart:async';
 
 import 'package:flutter/material.dart';
+import 'package:path_provider/path_provider.dart';
 import 'package:plugin/generated/rid_api.dart';
 import 'package:todo/views/menu.dart';
 import 'package:todo/views/todos.dart';
@@ -14,7 +15,10 @@ void configRid() {
 
 void main() async {
   configRid();
-  await Store.instance.msgSetAutoExpireCompletedTodos(false);
+
+  WidgetsFlutterBinding.ensureInitialized();
+  final appDir = await getApplicationSupportDirectory();
+  await Store.instance.msgInitialize(appDir.path);
   runApp(TodoApp());
 }
 
@@ -71,7 +75,7 @@ class _TodosPageState extends State<TodosPage> with StateAsync<TodosPage> {
   @override
   Widget build(BuildContext context) {
     debugPrint('  build: TodosPage');
-    final Filter filter = _store.filter;
+    final Filter filter = _store.filter.completion;
     final filteredTodos = _store.filteredTodos();
     final settings = _store.settings;
     debugPrint("filtered: \n  ${filteredTodos.join('\n  ')}");
@@ -0,0 +1,450 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row, NO_PARAMS};
+
+use crate::{Filter, Priority, Settings, Sort, Todo, TodoFilter, TAG_SEPARATOR};
+
+pub const DB_NAME: &str = "todo.sqlite";
+
+// Each migration upgrades the schema by one version which is tracked via `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    // 1: todo notes, due date and priority as well as sort order
+    "
+ALTER TABLE todos ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+ALTER TABLE todos ADD COLUMN due_date_millis INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE todos ADD COLUMN priority TEXT NOT NULL DEFAULT 'Medium';
+ALTER TABLE todo_state ADD COLUMN sort TEXT NOT NULL DEFAULT 'Created';
+",
+    // 2: todo tags and filtering by tags and title
+    "
+ALTER TABLE todos ADD COLUMN tags TEXT NOT NULL DEFAULT '';
+ALTER TABLE todo_state ADD COLUMN filter_tags TEXT NOT NULL DEFAULT '';
+ALTER TABLE todo_state ADD COLUMN filter_search TEXT NOT NULL DEFAULT '';
+",
+    // 3: configurable expiry tick rate
+    "
+ALTER TABLE todo_state ADD COLUMN expiry_tick_millis INTEGER NOT NULL DEFAULT 100;
+",
+];
+
+// Only one row is ever stored in the todo_state table
+const STATE_ROW_ID: u32 = 0;
+
+#[derive(Debug)]
+pub struct DB {
+    conn: Connection,
+}
+
+// -----------------
+// Persisted Store State
+// -----------------
+#[derive(Debug)]
+pub struct State {
+    pub last_added_id: u32,
+    pub filter: TodoFilter,
+    pub sort: Sort,
+    pub settings: Settings,
+}
+
+impl DB {
+    pub fn new(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)
+            .map_err(|err| anyhow!("Failed to open Database at: {}\nError: {}", path, err))?;
+
+        let db = Self { conn };
+        db.init_tables()?;
+        db.migrate()?;
+
+        Ok(db)
+    }
+
+    fn init_tables(&self) -> Result<()> {
+        self.conn
+            .execute_batch(
+                "
+BEGIN;
+CREATE TABLE IF NOT EXISTS todos (
+    id             INTEGER PRIMARY KEY,
+    title          TEXT,
+    completed      INTEGER,
+    expiry_millis  INTEGER
+);
+CREATE TABLE IF NOT EXISTS todo_state (
+    id                           INTEGER PRIMARY KEY,
+    last_added_id                INTEGER,
+    filter                       TEXT,
+    auto_expire_completed_todos  INTEGER,
+    completed_expiry_millis      INTEGER
+);
+COMMIT;
+",
+            )
+            .map_err(|err| anyhow!("Failed to create Database tables:\nError: {}", err))
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let version: i64 = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let next_version = (idx + 1) as i64;
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute_batch(migration)
+                .and_then(|_| tx.pragma_update(None, "user_version", &next_version))
+                .and_then(|_| tx.commit())
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to migrate Database to version {}:\nError: {}",
+                        next_version,
+                        err
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    // -----------------
+    // Insert/Update Todos and State
+    // -----------------
+    pub fn upsert_todo(&self, todo: &Todo) -> Result<usize> {
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO todos
+  (id, title, notes, due_date_millis, priority, tags, completed, expiry_millis)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);
+",
+                params![
+                    todo.id,
+                    todo.title,
+                    todo.notes,
+                    todo.due_date_millis as i64,
+                    priority_to_str(&todo.priority),
+                    join_tags(&todo.tags),
+                    todo.completed,
+                    todo.expiry_millis as i64
+                ],
+            )
+            .map_err(|err| anyhow!("Failed to save todo with id {}:\nError: {}", todo.id, err))
+    }
+
+    pub fn upsert_todos(&self, todos: &[Todo]) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut rows = 0;
+        for todo in todos {
+            rows += self.upsert_todo(todo)?;
+        }
+        tx.commit()
+            .map_err(|err| anyhow!("Failed to save todos:\nError: {}", err))?;
+        Ok(rows)
+    }
+
+    pub fn upsert_state(
+        &self,
+        last_added_id: u32,
+        filter: &TodoFilter,
+        sort: &Sort,
+        settings: &Settings,
+    ) -> Result<usize> {
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO todo_state
+  (id, last_added_id, filter, filter_tags, filter_search, sort,
+   auto_expire_completed_todos, completed_expiry_millis, expiry_tick_millis)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);
+",
+                params![
+                    STATE_ROW_ID,
+                    last_added_id,
+                    filter_to_str(&filter.completion),
+                    join_tags(&filter.tags),
+                    filter.search,
+                    sort_to_str(sort),
+                    settings.auto_expire_completed_todos,
+                    settings.completed_expiry_millis as i64,
+                    settings.expiry_tick_millis as i64
+                ],
+            )
+            .map_err(|err| anyhow!("Failed to save todo state:\nError: {}", err))
+    }
+
+    // -----------------
+    // Retrieving Todos and State
+    // -----------------
+    pub fn get_all_todos(&self) -> Result<Vec<Todo>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT id, title, notes, due_date_millis, priority, tags, completed, expiry_millis
+FROM todos
+ORDER BY id;
+",
+        )?;
+        let results = stmt.query_map(NO_PARAMS, try_extract_todo)?;
+        let todos: Vec<_> = results
+            .filter_map(|res| match res {
+                Ok(todo) => Some(todo),
+                Err(err) => {
+                    rid::error!("A todo couldn't be properly extracted", err.to_string());
+                    None
+                }
+            })
+            .collect();
+        Ok(todos)
+    }
+
+    /// Returns `None` if the state was never saved, i.e. the Database was just created.
+    pub fn get_state(&self) -> Result<Option<State>> {
+        self.conn
+            .query_row(
+                "
+SELECT last_added_id, filter, filter_tags, filter_search, sort,
+       auto_expire_completed_todos, completed_expiry_millis, expiry_tick_millis
+FROM todo_state
+WHERE id = (?1);
+",
+                params![STATE_ROW_ID],
+                try_extract_state,
+            )
+            .optional()
+            .map_err(|err| anyhow!("Failed to retrieve todo state:\nError: {}", err))
+    }
+
+    // -----------------
+    // Deleting Todos
+    // -----------------
+    pub fn delete_todo(&self, id: u32) -> Result<usize> {
+        self.conn
+            .execute(
+                "
+DELETE FROM todos
+WHERE id = (?1);
+",
+                params![id],
+            )
+            .map_err(|err| anyhow!("Failed to remove todo from table:\nError: {}", err))
+    }
+
+    pub fn delete_todos(&self, ids: &[u32]) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut rows = 0;
+        for id in ids {
+            rows += self.delete_todo(*id)?;
+        }
+        tx.commit()
+            .map_err(|err| anyhow!("Failed to remove todos from table:\nError: {}", err))?;
+        Ok(rows)
+    }
+
+    pub fn delete_completed_todos(&self) -> Result<usize> {
+        self.conn
+            .execute(
+                "
+DELETE FROM todos
+WHERE completed = 1;
+",
+                NO_PARAMS,
+            )
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to remove completed todos from table:\nError: {}",
+                    err
+                )
+            })
+    }
+}
+
+// -----------------
+// Sqlite helpers
+// -----------------
+fn try_extract_todo(row: &Row) -> rusqlite::Result<Todo> {
+    let due_date_millis: i64 = row.get(3)?;
+    let priority: String = row.get(4)?;
+    let tags: String = row.get(5)?;
+    let expiry_millis: i64 = row.get(7)?;
+    Ok(Todo {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        notes: row.get(2)?,
+        due_date_millis: due_date_millis as u64,
+        priority: str_to_priority(&priority),
+        tags: split_tags(&tags),
+        completed: row.get(6)?,
+        expiry_millis: expiry_millis as u64,
+        expires_at: None,
+    })
+}
+
+fn try_extract_state(row: &Row) -> rusqlite::Result<State> {
+    let filter: String = row.get(1)?;
+    let filter_tags: String = row.get(2)?;
+    let sort: String = row.get(4)?;
+    let completed_expiry_millis: i64 = row.get(6)?;
+    let expiry_tick_millis: i64 = row.get(7)?;
+    Ok(State {
+        last_added_id: row.get(0)?,
+        filter: TodoFilter {
+            completion: str_to_filter(&filter),
+            tags: split_tags(&filter_tags),
+            search: row.get(3)?,
+        },
+        sort: str_to_sort(&sort),
+        settings: Settings {
+            auto_expire_completed_todos: row.get(5)?,
+            completed_expiry_millis: completed_expiry_millis as u64,
+            expiry_tick_millis: expiry_tick_millis as u64,
+        },
+    })
+}
+
+fn filter_to_str(filter: &Filter) -> &'static str {
+    match filter {
+        Filter::Completed => "Completed",
+        Filter::Pending => "Pending",
+        Filter::All => "All",
+    }
+}
+
+fn str_to_filter(filter: &str) -> Filter {
+    match filter {
+        "Completed" => Filter::Completed,
+        "Pending" => Filter::Pending,
+        _ => Filter::All,
+    }
+}
+
+fn sort_to_str(sort: &Sort) -> &'static str {
+    match sort {
+        Sort::Created => "Created",
+        Sort::DueDate => "DueDate",
+        Sort::Priority => "Priority",
+    }
+}
+
+fn str_to_sort(sort: &str) -> Sort {
+    match sort {
+        "DueDate" => Sort::DueDate,
+        "Priority" => Sort::Priority,
+        _ => Sort::Created,
+    }
+}
+
+fn priority_to_str(priority: &Priority) -> &'static str {
+    match priority {
+        Priority::Low => "Low",
+        Priority::Medium => "Medium",
+        Priority::High => "High",
+    }
+}
+
+fn str_to_priority(priority: &str) -> Priority {
+    match priority {
+        "Low" => Priority::Low,
+        "High" => Priority::High,
+        _ => Priority::Medium,
+    }
+}
+
+fn join_tags(tags: &[String]) -> String {
+    tags.join(&TAG_SEPARATOR.to_string())
+}
+
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(TAG_SEPARATOR)
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+
+    // Schema of the tables as created before the Database was versioned
+    const SCHEMA_V0: &str = "
+CREATE TABLE todos (
+    id             INTEGER PRIMARY KEY,
+    title          TEXT,
+    completed      INTEGER,
+    expiry_millis  INTEGER
+);
+CREATE TABLE todo_state (
+    id                           INTEGER PRIMARY KEY,
+    last_added_id                INTEGER,
+    filter                       TEXT,
+    auto_expire_completed_todos  INTEGER,
+    completed_expiry_millis      INTEGER
+);
+INSERT INTO todos (id, title, completed, expiry_millis)
+VALUES (1, 'Learn Rust', 1, 2000);
+INSERT INTO todo_state
+  (id, last_added_id, filter, auto_expire_completed_todos, completed_expiry_millis)
+VALUES (0, 1, 'Completed', 1, 2000);
+";
+
+    /// Path of a Database file that doesn't exist yet and is unique to the test.
+    fn db_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("todo_{}_{}.sqlite", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn schema_version(db: &DB) -> i64 {
+        db.conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrates_unversioned_db() {
+        let path = db_path("migrates_unversioned_db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(SCHEMA_V0)
+            .unwrap();
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(schema_version(&db), MIGRATIONS.len() as i64);
+
+        let todos = db.get_all_todos().unwrap();
+        assert_eq!(todos.len(), 1);
+        let todo = &todos[0];
+        assert_eq!(todo.title, "Learn Rust");
+        assert!(todo.completed);
+        assert_eq!(todo.expiry_millis, 2000);
+        assert_eq!(todo.notes, "");
+        assert_eq!(todo.due_date_millis, 0);
+        assert_eq!(todo.priority, Priority::Medium);
+        assert!(todo.tags.is_empty());
+
+        let state = db.get_state().unwrap().unwrap();
+        assert_eq!(state.last_added_id, 1);
+        assert!(matches!(state.filter.completion, Filter::Completed));
+        assert!(state.filter.tags.is_empty());
+        assert_eq!(state.filter.search, "");
+        assert!(matches!(state.sort, Sort::Created));
+        assert!(state.settings.auto_expire_completed_todos);
+        assert_eq!(state.settings.completed_expiry_millis, 2000);
+        assert_eq!(state.settings.expiry_tick_millis, 100);
+
+        drop(db);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reopening_migrated_db_keeps_version() {
+        let path = db_path("reopening_migrated_db_keeps_version");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(schema_version(&db), MIGRATIONS.len() as i64);
+        assert!(db.get_state().unwrap().is_none());
+        drop(db);
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(schema_version(&db), MIGRATIONS.len() as i64);
+
+        drop(db);
+        fs::remove_file(&path).unwrap();
+    }
+}
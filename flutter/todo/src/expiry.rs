@@ -0,0 +1,251 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::Todo;
+
+// Guards against the worker spinning when a tiny tick interval is configured
+const MIN_TICK_INTERVAL: Duration = Duration::from_millis(10);
+
+// -----------------
+// Expiry Worker
+// -----------------
+
+/// Owns the thread running the [ExpiryScheduler] and ensures that at most one is running.
+#[derive(Debug)]
+pub struct ExpiryWorker {
+    scheduler: Arc<ExpiryScheduler>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ExpiryWorker {
+    pub fn new(tick_interval: Duration) -> Self {
+        Self {
+            scheduler: Arc::new(ExpiryScheduler::new(tick_interval)),
+            handle: None,
+        }
+    }
+
+    /// Starts a worker thread unless one is running already.
+    ///
+    /// Completed todos start counting down from their remaining `expiry_millis`.
+    pub fn start<F>(&mut self, todos: &mut [Todo], on_step: F)
+    where
+        F: Fn() -> Option<Instant> + Send + 'static,
+    {
+        if self.handle.is_some() {
+            return;
+        }
+        let generation = self.scheduler.enable();
+        self.scheduler.sync(todos);
+        let scheduler = self.scheduler.clone();
+        self.handle = Some(thread::spawn(move || {
+            eprintln!(
+                "rust: thread {:?} started auto expiring",
+                thread::current().id()
+            );
+            scheduler.run(generation, on_step);
+            eprintln!(
+                "rust: thread {:?} stopped auto expiring",
+                thread::current().id()
+            );
+        }));
+    }
+
+    /// Signals the worker thread to exit and returns its handle in order to join it.
+    /// The countdown of completed todos is paused at the millis they had left.
+    ///
+    /// The worker calls back into the Store, therefore the handle must not be joined while
+    /// holding the Store lock.
+    pub fn stop(&mut self, todos: &mut [Todo]) -> Option<JoinHandle<()>> {
+        self.scheduler.disable();
+        let now = Instant::now();
+        for todo in todos.iter_mut() {
+            todo.expiry_millis = todo.remaining_expiry_millis(now);
+            todo.expires_at = None;
+        }
+        self.handle.take()
+    }
+
+    pub fn sync(&self, todos: &mut [Todo]) {
+        self.scheduler.sync(todos);
+    }
+
+    pub fn set_tick_interval(&self, tick_interval: Duration) {
+        self.scheduler.set_tick_interval(tick_interval);
+    }
+}
+
+// -----------------
+// Expiry Scheduler
+// -----------------
+
+/// Wakes the worker whenever the earliest [Todo::expires_at] deadline or the next tick is due.
+///
+/// The deadlines themselves live on the todos. The scheduler only keeps track of the next one
+/// and is woken up early whenever that changes or the scheduler is disabled.
+#[derive(Debug)]
+pub struct ExpiryScheduler {
+    schedule: Mutex<Schedule>,
+    wakeup: Condvar,
+}
+
+#[derive(Debug)]
+struct Schedule {
+    enabled: bool,
+    /// Incremented whenever the scheduler is enabled so that a worker which is still shutting
+    /// down doesn't resume once a new one started
+    generation: u64,
+    changed: bool,
+    tick_interval: Duration,
+    next_deadline: Option<Instant>,
+}
+
+impl ExpiryScheduler {
+    fn new(tick_interval: Duration) -> Self {
+        Self {
+            schedule: Mutex::new(Schedule {
+                enabled: false,
+                generation: 0,
+                changed: false,
+                tick_interval: tick_interval.max(MIN_TICK_INTERVAL),
+                next_deadline: None,
+            }),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn enable(&self) -> u64 {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.enabled = true;
+        schedule.generation += 1;
+        schedule.next_deadline = None;
+        schedule.generation
+    }
+
+    fn disable(&self) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.enabled = false;
+        schedule.next_deadline = None;
+        self.notify(schedule);
+    }
+
+    fn set_tick_interval(&self, tick_interval: Duration) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.tick_interval = tick_interval.max(MIN_TICK_INTERVAL);
+        self.notify(schedule);
+    }
+
+    /// Assigns a deadline to completed todos that don't have one yet, based on their remaining
+    /// `expiry_millis`, and drops the deadlines of todos that were restarted.
+    fn sync(&self, todos: &mut [Todo]) {
+        let mut schedule = self.schedule.lock().unwrap();
+        if !schedule.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        for todo in todos.iter_mut() {
+            if !todo.completed {
+                todo.expires_at = None;
+            } else if todo.expires_at.is_none() {
+                todo.expires_at = Some(now + Duration::from_millis(todo.expiry_millis));
+            }
+        }
+
+        let next_deadline = todos.iter().filter_map(|x| x.expires_at).min();
+        if next_deadline != schedule.next_deadline {
+            schedule.next_deadline = next_deadline;
+            self.notify(schedule);
+        }
+    }
+
+    /// Runs until the scheduler is disabled or enabled again for another worker.
+    ///
+    /// On every step `on_step` is called to expire the todos whose deadline passed and returns
+    /// the earliest deadline of the ones that are still counting down.
+    fn run<F: Fn() -> Option<Instant>>(&self, generation: u64, on_step: F) {
+        let mut schedule = self.schedule.lock().unwrap();
+        while schedule.enabled && schedule.generation == generation {
+            // Never hold on to the schedule while calling back into the Store, since the Store
+            // locks the schedule when syncing todos
+            drop(schedule);
+            let next_deadline = on_step();
+            schedule = self.schedule.lock().unwrap();
+
+            // Todos changed while we weren't waiting, so we need to recalculate right away
+            if schedule.changed {
+                schedule.changed = false;
+                continue;
+            }
+            schedule.next_deadline = next_deadline;
+
+            let tick_interval = schedule.tick_interval;
+            schedule = match next_deadline {
+                Some(deadline) => {
+                    let timeout = deadline
+                        .saturating_duration_since(Instant::now())
+                        .min(tick_interval);
+                    self.wakeup.wait_timeout(schedule, timeout).unwrap().0
+                }
+                None => self.wakeup.wait(schedule).unwrap(),
+            };
+            schedule.changed = false;
+        }
+    }
+
+    fn notify(&self, mut schedule: MutexGuard<Schedule>) {
+        schedule.changed = true;
+        drop(schedule);
+        self.wakeup.notify_all();
+    }
+}
+
+/// Scales the `remaining` millis of an expiry time of `from_millis` to the same fraction of
+/// `to_millis`.
+pub fn rescale_millis(remaining: u64, from_millis: u64, to_millis: u64) -> u64 {
+    if from_millis == 0 {
+        return to_millis;
+    }
+    let remaining = remaining.min(from_millis) as u128;
+    (remaining * to_millis as u128 / from_millis as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescales_to_same_fraction() {
+        assert_eq!(rescale_millis(500, 1000, 2000), 1000);
+        assert_eq!(rescale_millis(1000, 1000, 3000), 3000);
+        assert_eq!(rescale_millis(0, 1000, 3000), 0);
+        assert_eq!(rescale_millis(250, 1000, 100), 25);
+    }
+
+    #[test]
+    fn rescales_from_zero_to_full_expiry() {
+        assert_eq!(rescale_millis(0, 0, 2000), 2000);
+        assert_eq!(rescale_millis(500, 0, 2000), 2000);
+    }
+
+    #[test]
+    fn rescales_to_zero() {
+        assert_eq!(rescale_millis(500, 1000, 0), 0);
+    }
+
+    #[test]
+    fn clamps_remaining_to_previous_expiry() {
+        assert_eq!(rescale_millis(5000, 1000, 2000), 2000);
+    }
+
+    #[test]
+    fn rescales_max_values_without_overflow() {
+        assert_eq!(rescale_millis(u64::MAX, u64::MAX, u64::MAX), u64::MAX);
+        assert_eq!(rescale_millis(u64::MAX / 2, u64::MAX, 1000), 499);
+        assert_eq!(rescale_millis(500, 1000, u64::MAX), u64::MAX / 2);
+        assert_eq!(rescale_millis(1, u64::MAX, u64::MAX), 1);
+    }
+}
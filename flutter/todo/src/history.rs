@@ -0,0 +1,266 @@
+use std::{collections::VecDeque, mem};
+
+use crate::Todo;
+
+const MAX_HISTORY: usize = 100;
+
+// -----------------
+// Edit
+// -----------------
+
+/// Describes how to revert a mutation of the Store's todos.
+/// Applying an edit returns another edit which reverts the changes made by it.
+#[derive(Debug)]
+pub enum Edit {
+    /// Todos that were removed and need to be added back.
+    Insert(Vec<Todo>),
+    /// Ids of todos that were added and need to be removed.
+    Remove(Vec<u32>),
+    /// Todos in the state they were in before they were updated.
+    Replace(Vec<Todo>),
+}
+
+impl Edit {
+    pub fn apply(self, todos: &mut Vec<Todo>) -> Edit {
+        match self {
+            Edit::Insert(inserted) => {
+                let ids = inserted.iter().map(|x| x.id).collect();
+                todos.extend(inserted.into_iter().map(restore));
+                Edit::Remove(ids)
+            }
+            Edit::Remove(ids) => {
+                let (removed, kept) = todos.drain(..).partition(|x| ids.contains(&x.id));
+                *todos = kept;
+                Edit::Insert(removed)
+            }
+            Edit::Replace(replacements) => {
+                let previous = replacements
+                    .into_iter()
+                    .filter_map(|replacement| {
+                        todos
+                            .iter_mut()
+                            .find(|x| x.id == replacement.id)
+                            .map(|todo| mem::replace(todo, restore(replacement)))
+                    })
+                    .collect();
+                Edit::Replace(previous)
+            }
+        }
+    }
+
+    /// Returns `true` if the edit doesn't affect any todos.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Edit::Insert(todos) | Edit::Replace(todos) => todos.is_empty(),
+            Edit::Remove(ids) => ids.is_empty(),
+        }
+    }
+
+    /// Ids of all todos affected by this edit.
+    pub fn ids(&self) -> Vec<u32> {
+        match self {
+            Edit::Insert(todos) | Edit::Replace(todos) => todos.iter().map(|x| x.id).collect(),
+            Edit::Remove(ids) => ids.clone(),
+        }
+    }
+}
+
+/// Drops the deadline a todo had when it was recorded, since it may have passed by now.
+/// The todo is rescheduled to count down from the `expiry_millis` it had left at that point.
+fn restore(mut todo: Todo) -> Todo {
+    todo.expires_at = None;
+    todo
+}
+
+// -----------------
+// History
+// -----------------
+
+/// Bounded undo/redo history of todo mutations.
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+        }
+    }
+
+    /// Records the edit that reverts a mutation which was just performed.
+    /// Any previously undone mutations cannot be redone after this.
+    ///
+    /// Edits that don't affect any todos are ignored since there is nothing to undo.
+    pub fn record(&mut self, edit: Edit) {
+        if edit.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.push_undo(edit);
+    }
+
+    /// Reverts the last mutation and returns the ids of the affected todos.
+    pub fn undo(&mut self, todos: &mut Vec<Todo>) -> Option<Vec<u32>> {
+        let edit = self.undo.pop_back()?;
+        let inverse = edit.apply(todos);
+        let ids = inverse.ids();
+        self.redo.push(inverse);
+        Some(ids)
+    }
+
+    /// Re-applies the last undone mutation and returns the ids of the affected todos.
+    pub fn redo(&mut self, todos: &mut Vec<Todo>) -> Option<Vec<u32>> {
+        let edit = self.redo.pop()?;
+        let inverse = edit.apply(todos);
+        let ids = inverse.ids();
+        self.push_undo(inverse);
+        Some(ids)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn push_undo(&mut self, edit: Edit) {
+        if self.undo.len() == MAX_HISTORY {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(edit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{Priority, NO_DUE_DATE};
+
+    fn todo(id: u32, title: &str) -> Todo {
+        Todo {
+            id,
+            title: title.to_string(),
+            notes: "".to_string(),
+            due_date_millis: NO_DUE_DATE,
+            priority: Priority::Medium,
+            tags: vec![],
+            completed: false,
+            expiry_millis: 1000,
+            expires_at: None,
+        }
+    }
+
+    fn titles(todos: &[Todo]) -> Vec<&str> {
+        todos.iter().map(|x| x.title.as_str()).collect()
+    }
+
+    #[test]
+    fn undo_and_redo_insert() {
+        let mut todos = vec![todo(1, "one")];
+        let mut history = History::new();
+
+        todos.push(todo(2, "two"));
+        history.record(Edit::Remove(vec![2]));
+
+        assert_eq!(history.undo(&mut todos), Some(vec![2]));
+        assert_eq!(titles(&todos), vec!["one"]);
+        assert!(!history.can_undo());
+        assert!(history.can_redo());
+
+        assert_eq!(history.redo(&mut todos), Some(vec![2]));
+        assert_eq!(titles(&todos), vec!["one", "two"]);
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undo_and_redo_remove() {
+        let mut todos = vec![todo(1, "one"), todo(2, "two")];
+        let mut history = History::new();
+
+        let removed = todos.remove(0);
+        history.record(Edit::Insert(vec![removed]));
+
+        assert_eq!(history.undo(&mut todos), Some(vec![1]));
+        assert_eq!(titles(&todos), vec!["two", "one"]);
+
+        assert_eq!(history.redo(&mut todos), Some(vec![1]));
+        assert_eq!(titles(&todos), vec!["two"]);
+    }
+
+    #[test]
+    fn undo_and_redo_replace_restores_without_deadline() {
+        let mut todos = vec![todo(1, "one")];
+        let mut history = History::new();
+
+        let previous = todos[0].clone();
+        todos[0].title = "uno".to_string();
+        todos[0].completed = true;
+        todos[0].expires_at = Some(Instant::now());
+        history.record(Edit::Replace(vec![previous]));
+
+        assert_eq!(history.undo(&mut todos), Some(vec![1]));
+        assert_eq!(titles(&todos), vec!["one"]);
+        assert!(!todos[0].completed);
+
+        assert_eq!(history.redo(&mut todos), Some(vec![1]));
+        assert_eq!(titles(&todos), vec!["uno"]);
+        assert!(todos[0].completed);
+        assert_eq!(todos[0].expires_at, None);
+    }
+
+    #[test]
+    fn nothing_to_undo_or_redo() {
+        let mut todos = vec![todo(1, "one")];
+        let mut history = History::new();
+
+        history.record(Edit::Replace(vec![]));
+        assert!(!history.can_undo());
+        assert_eq!(history.undo(&mut todos), None);
+        assert_eq!(history.redo(&mut todos), None);
+    }
+
+    #[test]
+    fn recording_clears_redo() {
+        let mut todos = vec![];
+        let mut history = History::new();
+
+        todos.push(todo(1, "one"));
+        history.record(Edit::Remove(vec![1]));
+        history.undo(&mut todos);
+        assert!(history.can_redo());
+
+        todos.push(todo(2, "two"));
+        history.record(Edit::Remove(vec![2]));
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(&mut todos), None);
+    }
+
+    #[test]
+    fn drops_oldest_edits_beyond_limit() {
+        let mut todos = vec![];
+        let mut history = History::new();
+
+        let count = MAX_HISTORY as u32 + 5;
+        for id in 1..=count {
+            todos.push(todo(id, "todo"));
+            history.record(Edit::Remove(vec![id]));
+        }
+
+        let mut undone = 0;
+        while history.undo(&mut todos).is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_HISTORY);
+        let ids: Vec<u32> = todos.iter().map(|x| x.id).collect();
+        assert_eq!(ids, (1..=5).collect::<Vec<u32>>());
+    }
+}
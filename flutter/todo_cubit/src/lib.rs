@@ -1,26 +1,42 @@
-use core::time;
 use std::{
-    sync::{RwLockReadGuard, RwLockWriteGuard},
-    thread,
+    cmp::Ordering,
+    fmt,
+    sync::RwLockWriteGuard,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+use expiry::{rescale_millis, ExpiryWorker};
+use history::{Edit, History};
 use rid::RidStore;
 
+mod expiry;
+mod history;
+
 const COMPLETED_EXPIRY_MILLIS: u64 = 7000;
-const EXPIRY_STEP: u64 = 10;
+const NO_DUE_DATE: u64 = 0;
+const TAG_SEPARATOR: char = ',';
+const ID_SEPARATOR: char = ',';
+const EXPIRY_TICK_MILLIS: u64 = 100;
 
 // -----------------
 // Store
 // -----------------
 #[rid::store]
-#[rid::structs(Todo, Settings)]
-#[rid::enums(Filter)]
-#[derive(Debug)]
+#[rid::structs(Todo, Settings, TodoFilter)]
+#[rid::enums(Sort)]
+#[derive(Debug, rid::Config)]
 pub struct Store {
     last_added_id: u32,
     todos: Vec<Todo>,
-    filter: Filter,
+    filter: TodoFilter,
+    sort: Sort,
     settings: Settings,
+
+    #[rid(skip)]
+    history: History,
+    #[rid(skip)]
+    expiry: ExpiryWorker,
 }
 
 impl RidStore<Msg> for Store {
@@ -28,35 +44,59 @@ impl RidStore<Msg> for Store {
         let first_todo = Todo {
             id: 0,
             title: "Learn Flutter".to_string(),
+            notes: "".to_string(),
+            due_date_millis: NO_DUE_DATE,
+            priority: Priority::Medium,
+            tags: vec![],
             completed: true,
             expiry_millis: COMPLETED_EXPIRY_MILLIS,
+            expires_at: None,
         };
         let second_todo = Todo {
             id: 1,
             title: "Learn Rust".to_string(),
+            notes: "".to_string(),
+            due_date_millis: NO_DUE_DATE,
+            priority: Priority::Medium,
+            tags: vec![],
             completed: true,
             expiry_millis: COMPLETED_EXPIRY_MILLIS,
+            expires_at: None,
         };
         let third_todo = Todo {
             id: 2,
             title: "Learn Rid".to_string(),
+            notes: "".to_string(),
+            due_date_millis: NO_DUE_DATE,
+            priority: Priority::Medium,
+            tags: vec![],
             completed: false,
             expiry_millis: COMPLETED_EXPIRY_MILLIS,
+            expires_at: None,
         };
         let fourth_todo = Todo {
             id: 3,
             title: "Build Awesome Apps".to_string(),
+            notes: "".to_string(),
+            due_date_millis: NO_DUE_DATE,
+            priority: Priority::Medium,
+            tags: vec![],
             completed: false,
             expiry_millis: COMPLETED_EXPIRY_MILLIS,
+            expires_at: None,
         };
         Self {
             last_added_id: 3,
             todos: vec![first_todo, second_todo, third_todo, fourth_todo],
-            filter: Filter::All,
+            filter: Filter::All.into(),
+            sort: Sort::Created,
             settings: Settings {
                 auto_expire_completed_todos: false,
                 completed_expiry_millis: COMPLETED_EXPIRY_MILLIS,
+                expiry_tick_millis: EXPIRY_TICK_MILLIS,
             },
+            history: History::new(),
+            expiry: ExpiryWorker::new(Duration::from_millis(EXPIRY_TICK_MILLIS)),
         }
     }
 
@@ -64,148 +104,346 @@ impl RidStore<Msg> for Store {
         use Msg::*;
         match msg {
             AddTodo(title) => {
-                self.last_added_id += 1;
-                let todo = Todo {
-                    id: self.last_added_id,
-                    title,
-                    completed: false,
-                    expiry_millis: COMPLETED_EXPIRY_MILLIS,
-                };
-                self.todos.push(todo);
-                rid::post(Reply::AddedTodo(req_id, self.last_added_id.to_string()));
+                let result = validate_title(&title).map(|_| {
+                    self.last_added_id += 1;
+                    let todo = Todo {
+                        id: self.last_added_id,
+                        title,
+                        notes: "".to_string(),
+                        due_date_millis: NO_DUE_DATE,
+                        priority: Priority::Medium,
+                        tags: vec![],
+                        completed: false,
+                        expiry_millis: self.settings.completed_expiry_millis,
+                        expires_at: None,
+                    };
+                    self.todos.push(todo);
+                    self.history.record(Edit::Remove(vec![self.last_added_id]));
+                    Reply::AddedTodo(req_id, self.last_added_id.to_string())
+                });
+                post_result(req_id, result);
             }
             RemoveTodo(id) => {
-                self.remove_todo(id);
-                rid::post(Reply::RemovedTodo(req_id, self.last_added_id.to_string()));
+                let result = self.remove_todo(id).map(|todo| {
+                    self.history.record(Edit::Insert(vec![todo]));
+                    Reply::RemovedTodo(req_id, id.to_string())
+                });
+                post_result(req_id, result);
             }
 
             RemoveCompleted => {
-                self.todos.retain(|todo| !todo.completed);
-                rid::post(Reply::RemovedCompleted(req_id));
+                let (completed, pending): (Vec<Todo>, Vec<Todo>) =
+                    self.todos.drain(..).partition(|x| x.completed);
+                self.todos = pending;
+                let ids: Vec<u32> = completed.iter().map(|x| x.id).collect();
+                self.history.record(Edit::Insert(completed));
+                rid::post(Reply::RemovedCompleted(req_id, join_ids(&ids)));
             }
 
             CompleteTodo(id) => {
-                self.update_todo(id, |todo| todo.set_completed(true));
-                rid::post(Reply::CompletedTodo(req_id, id.to_string()));
+                let expiry_millis = self.settings.completed_expiry_millis;
+                let result = self.update_todo(id, |todo| todo.set_completed(true, expiry_millis));
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::CompletedTodo(req_id, id.to_string())),
+                );
             }
             RestartTodo(id) => {
-                self.update_todo(id, |todo| todo.set_completed(false));
-                rid::post(Reply::RestartedTodo(req_id, id.to_string()));
+                let expiry_millis = self.settings.completed_expiry_millis;
+                let result = self.update_todo(id, |todo| todo.set_completed(false, expiry_millis));
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::RestartedTodo(req_id, id.to_string())),
+                );
             }
             ToggleTodo(id) => {
-                self.update_todo(id, |todo| todo.set_completed(!todo.completed));
-                rid::post(Reply::ToggledTodo(req_id, id.to_string()));
+                let expiry_millis = self.settings.completed_expiry_millis;
+                let result = self.update_todo(id, |todo| {
+                    todo.set_completed(!todo.completed, expiry_millis)
+                });
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::ToggledTodo(req_id, id.to_string())),
+                );
+            }
+
+            EditTodoTitle(id, title) => {
+                let result = validate_title(&title)
+                    .and_then(|_| self.update_todo(id, |todo| todo.title = title));
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::EditedTodoTitle(req_id, id.to_string())),
+                );
+            }
+            SetTodoNotes(id, notes) => {
+                let result = self.update_todo(id, |todo| todo.notes = notes);
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::SetTodoNotes(req_id, id.to_string())),
+                );
+            }
+            SetTodoDueDate(id, due_date_millis) => {
+                let result = self.update_todo(id, |todo| todo.due_date_millis = due_date_millis);
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::SetTodoDueDate(req_id, id.to_string())),
+                );
+            }
+            SetTodoPriority(id, priority) => {
+                let result = self.update_todo(id, |todo| todo.priority = priority);
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::SetTodoPriority(req_id, id.to_string())),
+                );
+            }
+            AddTag(id, tag) => {
+                let result = normalize_tag(&tag)
+                    .and_then(|tag| self.update_todo(id, |todo| todo.add_tag(tag)));
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::AddedTag(req_id, id.to_string())),
+                );
+            }
+            RemoveTag(id, tag) => {
+                let result = normalize_tag(&tag)
+                    .and_then(|tag| self.update_todo(id, |todo| todo.remove_tag(&tag)));
+                post_result(
+                    req_id,
+                    result.map(|_| Reply::RemovedTag(req_id, id.to_string())),
+                );
             }
 
             CompleteAll => {
-                self.todos.iter_mut().for_each(|x| x.set_completed(true));
-                rid::post(Reply::CompletedAll(req_id));
+                let ids = self.set_all_completed(true);
+                rid::post(Reply::CompletedAll(req_id, join_ids(&ids)));
             }
             RestartAll => {
-                self.todos.iter_mut().for_each(|x| x.set_completed(false));
-                rid::post(Reply::RestartedAll(req_id));
+                let ids = self.set_all_completed(false);
+                rid::post(Reply::RestartedAll(req_id, join_ids(&ids)));
+            }
+
+            Undo => {
+                let result = self
+                    .history
+                    .undo(&mut self.todos)
+                    .ok_or(TodoError::NothingToUndo);
+                post_result(
+                    req_id,
+                    result.map(|ids| Reply::Undone(req_id, join_ids(&ids))),
+                );
+            }
+            Redo => {
+                let result = self
+                    .history
+                    .redo(&mut self.todos)
+                    .ok_or(TodoError::NothingToRedo);
+                post_result(
+                    req_id,
+                    result.map(|ids| Reply::Redone(req_id, join_ids(&ids))),
+                );
             }
 
             SetFilter(filter) => {
-                self.filter = filter;
+                self.filter = filter.into();
                 rid::post(Reply::SetFilter(req_id));
             }
+            SetFilterSearch(search) => {
+                self.filter.search = search;
+                rid::post(Reply::SetFilterSearch(req_id));
+            }
+            AddFilterTag(tag) => {
+                let result = normalize_tag(&tag).map(|tag| {
+                    if !self.filter.tags.contains(&tag) {
+                        self.filter.tags.push(tag);
+                    }
+                    Reply::AddedFilterTag(req_id)
+                });
+                post_result(req_id, result);
+            }
+            RemoveFilterTag(tag) => {
+                let result = normalize_tag(&tag).map(|tag| {
+                    self.filter.tags.retain(|x| x != &tag);
+                    Reply::RemovedFilterTag(req_id)
+                });
+                post_result(req_id, result);
+            }
+            SetSort(sort) => {
+                self.sort = sort;
+                rid::post(Reply::SetSort(req_id));
+            }
             SetAutoExpireCompletedTodos(expire) => {
-                self.set_auto_expire_completed_todos(expire);
-                rid::post(Reply::SetAutoExpireCompletedTodos(req_id));
+                let stopping = self.set_auto_expire_completed_todos(expire);
+                match stopping {
+                    // The worker may be waiting for the Store lock that we are holding right now,
+                    // therefore we join it on a separate thread and only reply once it exited
+                    Some(worker) => {
+                        thread::spawn(move || {
+                            if worker.join().is_err() {
+                                eprintln!("rust: auto expiry thread panicked");
+                            }
+                            rid::post(Reply::SetAutoExpireCompletedTodos(req_id));
+                        });
+                    }
+                    None => rid::post(Reply::SetAutoExpireCompletedTodos(req_id)),
+                }
+            }
+            SetCompletedExpiryMillis(millis) => {
+                let previous = self.settings.completed_expiry_millis;
+                self.settings.completed_expiry_millis = millis;
+
+                // Todos that are already counting down keep the fraction of their expiry time
+                // that was left, i.e. a todo that was halfway expired still is after the change
+                let now = Instant::now();
+                for todo in self.todos.iter_mut().filter(|x| x.completed) {
+                    let remaining = todo.remaining_expiry_millis(now);
+                    todo.expiry_millis = rescale_millis(remaining, previous, millis);
+                    // Rescheduled from the rescaled expiry_millis when syncing below
+                    todo.expires_at = None;
+                }
+                rid::post(Reply::SetCompletedExpiryMillis(req_id));
+            }
+            SetExpiryTickMillis(millis) => {
+                self.settings.expiry_tick_millis = millis;
+                self.expiry.set_tick_interval(Duration::from_millis(millis));
+                rid::post(Reply::SetExpiryTickMillis(req_id));
             }
         };
+        // Completed todos may have been added, removed or restarted
+        self.expiry.sync(&mut self.todos);
     }
 }
 
 #[rid::export]
 #[rid::structs(Todo)]
 impl Store {
-    fn remove_todo(&mut self, id: u32) {
-        let mut enumerated = self.todos.iter().enumerate();
-        let idx = match enumerated.find(|(_, todo)| todo.id == id) {
-            Some((idx, _)) => idx,
-            None => return eprintln!("Could not find Todo with id '{}'", id),
-        };
-        self.todos.remove(idx);
+    fn remove_todo(&mut self, id: u32) -> Result<Todo, TodoError> {
+        match self.todos.iter().position(|todo| todo.id == id) {
+            Some(idx) => Ok(self.todos.remove(idx)),
+            None => Err(TodoError::TodoNotFound(id)),
+        }
     }
 
-    fn update_todo<F: FnOnce(&mut Todo)>(&mut self, id: u32, update: F) {
-        match self.todos.iter_mut().find(|x| x.id == id) {
-            Some(todo) => update(todo),
-            None => eprintln!("Could not find Todo with id '{}'", id),
-        };
+    /// Records the todo's previous state in the history if the update changed it.
+    fn update_todo<F: FnOnce(&mut Todo)>(&mut self, id: u32, update: F) -> Result<(), TodoError> {
+        let todo = self
+            .todos
+            .iter_mut()
+            .find(|x| x.id == id)
+            .ok_or(TodoError::TodoNotFound(id))?;
+        let previous = todo.clone();
+        update(todo);
+        if *todo != previous {
+            self.history.record(Edit::Replace(vec![previous]));
+        }
+        Ok(())
+    }
+
+    /// Completes or restarts all todos that aren't in that state yet and returns their ids.
+    fn set_all_completed(&mut self, completed: bool) -> Vec<u32> {
+        let expiry_millis = self.settings.completed_expiry_millis;
+        let mut previous = vec![];
+        for todo in self.todos.iter_mut().filter(|x| x.completed != completed) {
+            previous.push(todo.clone());
+            todo.set_completed(completed, expiry_millis);
+        }
+        let ids: Vec<u32> = previous.iter().map(|x| x.id).collect();
+        self.history.record(Edit::Replace(previous));
+        ids
     }
 
     #[rid::export]
     fn filtered_todos(&self) -> Vec<&Todo> {
-        let mut vec: Vec<&Todo> = match self.filter {
-            Filter::Completed => self.todos.iter().filter(|x| x.completed).collect(),
-            Filter::Pending => self.todos.iter().filter(|x| !x.completed).collect(),
-            Filter::All => self.todos.iter().collect(),
-        };
-        vec.sort();
+        let mut vec: Vec<&Todo> = self
+            .todos
+            .iter()
+            .filter(|x| self.filter.matches(x))
+            .collect();
+        vec.sort_by(|a, b| self.sort.compare(a, b));
         vec
     }
 
+    #[rid::export]
+    fn todo_ids(&self) -> Vec<u32> {
+        self.todos.iter().map(|x| x.id).collect()
+    }
+
     #[rid::export]
     fn todo_by_id(&self, id: u32) -> Option<&Todo> {
         self.todos.iter().find(|x| x.id == id)
     }
 
-    // The below read/write wrappers help with auto complete since procmacros
-    // aren't very well supported by the rust analyzer yet
-    fn read() -> RwLockReadGuard<'static, Store> {
-        store::read()
+    #[rid::export]
+    fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    #[rid::export]
+    fn can_redo(&self) -> bool {
+        self.history.can_redo()
     }
 
+    // The below write wrapper helps with auto complete since procmacros
+    // aren't very well supported by the rust analyzer yet
     fn write() -> RwLockWriteGuard<'static, Store> {
         store::write()
     }
 
-    pub fn set_auto_expire_completed_todos(&mut self, expire: bool) {
+    /// Starts the expiry worker unless it is running already.
+    /// When turning auto expiry off the handle of the stopping worker is returned.
+    pub fn set_auto_expire_completed_todos(&mut self, expire: bool) -> Option<JoinHandle<()>> {
         self.settings.auto_expire_completed_todos = expire;
         if expire {
-            thread::spawn(move || {
-                eprintln!(
-                    "rust: thread {:?} started auto expiring",
-                    thread::current().id()
-                );
-                while Store::read().settings.auto_expire_completed_todos {
-                    thread::sleep(time::Duration::from_millis(EXPIRY_STEP));
-                    {
-                        let ids_to_update: Vec<(u32, bool)> = Store::write()
-                            .todos
-                            .iter_mut()
-                            .filter(|x| x.completed)
-                            .map(|x: &mut Todo| {
-                                let next_value = x.expiry_millis - EXPIRY_STEP;
-                                if next_value <= 0 {
-                                    (x.id, true)
-                                } else {
-                                    x.expiry_millis = next_value;
-                                    (x.id, false)
-                                }
-                            })
-                            .collect();
-
-                        for (id, remove) in ids_to_update {
-                            if remove {
-                                Store::write().remove_todo(id);
-                                rid::post(Reply::CompletedTodoExpired);
-                            } else {
-                                rid::post(Reply::Tick(id.to_string()));
-                            }
-                        }
-                    }
-                }
-                eprintln!(
-                    "rust: thread {:?} stopped auto expiring",
-                    thread::current().id()
-                );
-            });
+            self.expiry.start(&mut self.todos, expire_todos);
+            None
+        } else {
+            self.expiry.stop(&mut self.todos)
+        }
+    }
+}
+
+// -----------------
+// Expiring Todos
+// -----------------
+/// Removes all todos whose deadline passed in one batch and updates the remaining
+/// `expiry_millis` of the ones still counting down.
+/// Returns the earliest deadline that is left.
+fn expire_todos() -> Option<Instant> {
+    let now = Instant::now();
+    let (expired, remaining, next_deadline) = {
+        let mut store = Store::write();
+        let store = &mut *store;
+
+        let (mut expired_todos, todos): (Vec<Todo>, Vec<Todo>) =
+            store.todos.drain(..).partition(|x| x.is_expired(now));
+        store.todos = todos;
+
+        let mut remaining = vec![];
+        for todo in store.todos.iter_mut().filter(|x| x.expires_at.is_some()) {
+            todo.expiry_millis = todo.remaining_expiry_millis(now);
+            remaining.push(todo.id);
+        }
+        let next_deadline = store.todos.iter().filter_map(|x| x.expires_at).min();
+
+        let expired: Vec<u32> = expired_todos.iter().map(|x| x.id).collect();
+        if !expired.is_empty() {
+            // Restore undone expiries with a fresh countdown, otherwise the todos would expire
+            // again right away
+            let expiry_millis = store.settings.completed_expiry_millis;
+            expired_todos
+                .iter_mut()
+                .for_each(|x| x.set_completed(true, expiry_millis));
+            store.history.record(Edit::Insert(expired_todos));
         }
+        (expired, remaining, next_deadline)
+    };
+
+    if !expired.is_empty() {
+        rid::post(Reply::CompletedTodoExpired(join_ids(&expired)));
+    }
+    for id in remaining {
+        rid::post(Reply::Tick(id.to_string()));
     }
+    next_deadline
 }
 
 // -----------------
@@ -216,30 +454,135 @@ impl Store {
 pub struct Settings {
     auto_expire_completed_todos: bool,
     completed_expiry_millis: u64,
+    /// How often [Reply::Tick] is posted for todos that are about to expire
+    expiry_tick_millis: u64,
 }
 
 // -----------------
 // Todo Model
 // -----------------
 #[rid::model]
-#[derive(PartialEq, Eq, PartialOrd, Debug)]
+#[rid::enums(Priority)]
+#[derive(PartialEq, Eq, Debug, Clone, rid::Config)]
 pub struct Todo {
     id: u32,
     title: String,
+    /// Empty if the todo has no notes
+    notes: String,
+    /// Millis since [UNIX_EPOCH](std::time::UNIX_EPOCH) or [NO_DUE_DATE]
+    due_date_millis: u64,
+    priority: Priority,
+    /// Normalized via [normalize_tag] and sorted
+    tags: Vec<String>,
     completed: bool,
+    /// Millis left until the todo expires, updated on every tick while it is counting down
     expiry_millis: u64,
+    /// Set while the todo is completed and auto expiry is running
+    #[rid(skip)]
+    expires_at: Option<Instant>,
 }
 
 impl Todo {
-    fn set_completed(&mut self, completed: bool) {
+    /// Restarts the countdown of the todo at `expiry_millis` once it is scheduled again.
+    /// Does nothing if the todo already is in that state to not reset a running countdown.
+    fn set_completed(&mut self, completed: bool, expiry_millis: u64) {
+        if self.completed == completed {
+            return;
+        }
         self.completed = completed;
-        self.expiry_millis = COMPLETED_EXPIRY_MILLIS;
+        self.expiry_millis = expiry_millis;
+        self.expires_at = None;
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.expires_at, Some(deadline) if deadline <= now)
+    }
+
+    /// Never less than zero, even if the deadline passed a while ago.
+    fn remaining_expiry_millis(&self, now: Instant) -> u64 {
+        match self.expires_at {
+            Some(deadline) => deadline.saturating_duration_since(now).as_millis() as u64,
+            None => self.expiry_millis,
+        }
+    }
+
+    fn add_tag(&mut self, tag: String) {
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+            self.tags.sort();
+        }
+    }
+
+    fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|x| x != tag);
+    }
+}
+
+/// Replies affecting multiple todos carry their ids separated by [ID_SEPARATOR], since reply
+/// data is always a string.
+fn join_ids(ids: &[u32]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(&ID_SEPARATOR.to_string())
+}
+
+/// Tags are matched case-insensitively and may not contain the [TAG_SEPARATOR] used to store
+/// them. Fails for tags that are empty once normalized.
+fn normalize_tag(tag: &str) -> Result<String, TodoError> {
+    let normalized = tag.trim().to_lowercase().replace(TAG_SEPARATOR, "");
+    if normalized.is_empty() {
+        Err(TodoError::InvalidTag(tag.to_string()))
+    } else {
+        Ok(normalized)
     }
 }
 
-impl Ord for Todo {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.id.cmp(&other.id)
+fn validate_title(title: &str) -> Result<(), TodoError> {
+    if title.trim().is_empty() {
+        Err(TodoError::EmptyTitle)
+    } else {
+        Ok(())
+    }
+}
+
+// -----------------
+// Priority
+// -----------------
+#[rid::model]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+// -----------------
+// Sort
+// -----------------
+#[rid::model]
+#[derive(Clone, Debug)]
+pub enum Sort {
+    Created,
+    DueDate,
+    Priority,
+}
+
+impl Sort {
+    fn compare(&self, a: &Todo, b: &Todo) -> Ordering {
+        let ordering = match self {
+            Sort::Created => Ordering::Equal,
+            // Todos without a due date go last
+            Sort::DueDate => match (a.due_date_millis, b.due_date_millis) {
+                (NO_DUE_DATE, NO_DUE_DATE) => Ordering::Equal,
+                (NO_DUE_DATE, _) => Ordering::Greater,
+                (_, NO_DUE_DATE) => Ordering::Less,
+                (a, b) => a.cmp(&b),
+            },
+            // Highest priority goes first
+            Sort::Priority => b.priority.cmp(&a.priority),
+        };
+        ordering.then(a.id.cmp(&b.id))
     }
 }
 
@@ -254,11 +597,54 @@ pub enum Filter {
     All,
 }
 
+// -----------------
+// TodoFilter
+// -----------------
+
+/// Combines completion state, tags and a title search.
+/// The [Filter] variants serve as presets which match on completion state only.
+#[rid::model]
+#[rid::enums(Filter)]
+#[derive(Clone, Debug)]
+pub struct TodoFilter {
+    completion: Filter,
+    /// Todos need to have all of these tags
+    tags: Vec<String>,
+    /// Case-insensitive substring of the todo title, matches all todos when empty
+    search: String,
+}
+
+impl TodoFilter {
+    fn matches(&self, todo: &Todo) -> bool {
+        let completion = match self.completion {
+            Filter::Completed => todo.completed,
+            Filter::Pending => !todo.completed,
+            Filter::All => true,
+        };
+        completion
+            && self.tags.iter().all(|tag| todo.tags.contains(tag))
+            && todo
+                .title
+                .to_lowercase()
+                .contains(&self.search.to_lowercase())
+    }
+}
+
+impl From<Filter> for TodoFilter {
+    fn from(completion: Filter) -> Self {
+        Self {
+            completion,
+            tags: vec![],
+            search: "".to_string(),
+        }
+    }
+}
+
 // -----------------
 // Msg
 // -----------------
 #[rid::message(Reply)]
-#[rid::enums(Filter)]
+#[rid::enums(Filter, Sort, Priority)]
 #[derive(Debug)]
 pub enum Msg {
     AddTodo(String),
@@ -271,8 +657,26 @@ pub enum Msg {
     CompleteAll,
     RestartAll,
 
+    EditTodoTitle(u32, String),
+    SetTodoNotes(u32, String),
+    /// Millis since [UNIX_EPOCH](std::time::UNIX_EPOCH), pass [NO_DUE_DATE] to clear it
+    SetTodoDueDate(u32, u64),
+    SetTodoPriority(u32, Priority),
+    AddTag(u32, String),
+    RemoveTag(u32, String),
+
+    Undo,
+    Redo,
+
+    /// Resets tags and search of the current filter
     SetFilter(Filter),
+    SetFilterSearch(String),
+    AddFilterTag(String),
+    RemoveFilterTag(String),
+    SetSort(Sort),
     SetAutoExpireCompletedTodos(bool),
+    SetCompletedExpiryMillis(u64),
+    SetExpiryTickMillis(u64),
 }
 
 // -----------------
@@ -281,20 +685,77 @@ pub enum Msg {
 #[rid::reply]
 pub enum Reply {
     // Message Replies
+    /// The message failed and didn't change anything, includes the encoded [TodoError]
+    Failed(u64, String),
     AddedTodo(u64, String),
     RemovedTodo(u64, String),
-    RemovedCompleted(u64),
+    RemovedCompleted(u64, String),
 
     CompletedTodo(u64, String),
     RestartedTodo(u64, String),
     ToggledTodo(u64, String),
-    CompletedAll(u64),
-    RestartedAll(u64),
+    CompletedAll(u64, String),
+    RestartedAll(u64, String),
+
+    EditedTodoTitle(u64, String),
+    SetTodoNotes(u64, String),
+    SetTodoDueDate(u64, String),
+    SetTodoPriority(u64, String),
+    AddedTag(u64, String),
+    RemovedTag(u64, String),
+
+    Undone(u64, String),
+    Redone(u64, String),
 
     SetFilter(u64),
+    SetFilterSearch(u64),
+    AddedFilterTag(u64),
+    RemovedFilterTag(u64),
+    SetSort(u64),
     SetAutoExpireCompletedTodos(u64),
+    SetCompletedExpiryMillis(u64),
+    SetExpiryTickMillis(u64),
 
     // Application Events
-    CompletedTodoExpired,
+    CompletedTodoExpired(String),
     Tick(String),
 }
+
+// -----------------
+// Errors
+// -----------------
+
+/// Reasons for a message to fail, posted as [Reply::Failed].
+///
+/// Replies can only carry a string, therefore the error is encoded as `<Kind>` or
+/// `<Kind>:<detail>`, i.e. `TodoNotFound:3`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TodoError {
+    TodoNotFound(u32),
+    EmptyTitle,
+    InvalidTag(String),
+    NothingToUndo,
+    NothingToRedo,
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TodoError::TodoNotFound(id) => write!(f, "TodoNotFound:{}", id),
+            TodoError::EmptyTitle => write!(f, "EmptyTitle"),
+            TodoError::InvalidTag(tag) => write!(f, "InvalidTag:{}", tag),
+            TodoError::NothingToUndo => write!(f, "NothingToUndo"),
+            TodoError::NothingToRedo => write!(f, "NothingToRedo"),
+        }
+    }
+}
+
+impl std::error::Error for TodoError {}
+
+/// Posts the reply of a message that succeeded or [Reply::Failed] with the reason it didn't.
+fn post_result(req_id: u64, result: Result<Reply, TodoError>) {
+    match result {
+        Ok(reply) => rid::post(reply),
+        Err(err) => rid::post(Reply::Failed(req_id, err.to_string())),
+    }
+}
@@ -3,8 +3,10 @@ mod replies;
 
 use rid::RidStore;
 use serde::Serialize;
+use std::fmt;
 
 const COMPLETED_EXPIRY_MILLIS: u64 = 7000;
+const ID_SEPARATOR: char = ',';
 
 // -----------------
 // Store
@@ -72,35 +74,49 @@ impl RidStore<Msg> for Store {
                 replies::post(Reply::AddedTodo(req_id, self.last_added_id.to_string()));
             }
             RemoveTodo(id) => {
-                self.remove_todo(id);
-                replies::post(Reply::RemovedTodo(req_id, self.last_added_id.to_string()));
+                let result = self
+                    .remove_todo(id)
+                    .map(|_| Reply::RemovedTodo(req_id, id.to_string()));
+                post_result(req_id, result);
             }
 
             RemoveCompleted => {
+                let ids: Vec<u32> = self
+                    .todos
+                    .iter()
+                    .filter(|x| x.completed)
+                    .map(|x| x.id)
+                    .collect();
                 self.todos.retain(|todo| !todo.completed);
-                replies::post(Reply::RemovedCompleted(req_id));
+                replies::post(Reply::RemovedCompleted(req_id, join_ids(&ids)));
             }
 
             CompleteTodo(id) => {
-                self.update_todo(id, |todo| todo.set_completed(true));
-                replies::post(Reply::CompletedTodo(req_id, id.to_string()));
+                let result = self
+                    .update_todo(id, |todo| todo.set_completed(true))
+                    .map(|_| Reply::CompletedTodo(req_id, id.to_string()));
+                post_result(req_id, result);
             }
             RestartTodo(id) => {
-                self.update_todo(id, |todo| todo.set_completed(false));
-                replies::post(Reply::RestartedTodo(req_id, id.to_string()));
+                let result = self
+                    .update_todo(id, |todo| todo.set_completed(false))
+                    .map(|_| Reply::RestartedTodo(req_id, id.to_string()));
+                post_result(req_id, result);
             }
             ToggleTodo(id) => {
-                self.update_todo(id, |todo| todo.set_completed(!todo.completed));
-                replies::post(Reply::ToggledTodo(req_id, id.to_string()));
+                let result = self
+                    .update_todo(id, |todo| todo.set_completed(!todo.completed))
+                    .map(|_| Reply::ToggledTodo(req_id, id.to_string()));
+                post_result(req_id, result);
             }
 
             CompleteAll => {
-                self.todos.iter_mut().for_each(|x| x.set_completed(true));
-                replies::post(Reply::CompletedAll(req_id));
+                let ids = self.set_all_completed(true);
+                replies::post(Reply::CompletedAll(req_id, join_ids(&ids)));
             }
             RestartAll => {
-                self.todos.iter_mut().for_each(|x| x.set_completed(false));
-                replies::post(Reply::RestartedAll(req_id));
+                let ids = self.set_all_completed(false);
+                replies::post(Reply::RestartedAll(req_id, join_ids(&ids)));
             }
 
             SetFilter(filter) => {
@@ -117,20 +133,34 @@ impl RidStore<Msg> for Store {
 #[rid::export]
 #[rid::structs(Todo)]
 impl Store {
-    fn remove_todo(&mut self, id: u32) {
-        let mut enumerated = self.todos.iter().enumerate();
-        let idx = match enumerated.find(|(_, todo)| todo.id == id) {
-            Some((idx, _)) => idx,
-            None => return eprintln!("Could not find Todo with id '{}'", id),
-        };
+    fn remove_todo(&mut self, id: u32) -> Result<(), TodoError> {
+        let idx = self
+            .todos
+            .iter()
+            .position(|todo| todo.id == id)
+            .ok_or(TodoError::TodoNotFound(id))?;
         self.todos.remove(idx);
+        Ok(())
     }
 
-    fn update_todo<F: FnOnce(&mut Todo)>(&mut self, id: u32, update: F) {
-        match self.todos.iter_mut().find(|x| x.id == id) {
-            Some(todo) => update(todo),
-            None => eprintln!("Could not find Todo with id '{}'", id),
-        };
+    fn update_todo<F: FnOnce(&mut Todo)>(&mut self, id: u32, update: F) -> Result<(), TodoError> {
+        let todo = self
+            .todos
+            .iter_mut()
+            .find(|x| x.id == id)
+            .ok_or(TodoError::TodoNotFound(id))?;
+        update(todo);
+        Ok(())
+    }
+
+    /// Completes or restarts all todos that aren't in that state yet and returns their ids.
+    fn set_all_completed(&mut self, completed: bool) -> Vec<u32> {
+        let mut ids = vec![];
+        for x in self.todos.iter_mut().filter(|x| x.completed != completed) {
+            x.set_completed(completed);
+            ids.push(x.id);
+        }
+        ids
     }
 
     fn filtered_todos(&self) -> Vec<&Todo> {
@@ -231,17 +261,60 @@ pub enum Reply {
     // Message Replies
     AddedTodo(u64, String),
     RemovedTodo(u64, String),
-    RemovedCompleted(u64),
+    RemovedCompleted(u64, String),
 
     CompletedTodo(u64, String),
     RestartedTodo(u64, String),
     ToggledTodo(u64, String),
-    CompletedAll(u64),
-    RestartedAll(u64),
+    CompletedAll(u64, String),
+    RestartedAll(u64, String),
 
     SetFilter(u64),
 
     // Application Events
     CompletedTodoExpired,
     Tick(String),
+
+    /// The message failed and didn't change anything, includes the encoded [TodoError]
+    Failed(u64, String),
+}
+
+// -----------------
+// Errors
+// -----------------
+
+/// Reasons for a message to fail, posted as [Reply::Failed].
+///
+/// Replies can only carry a string, therefore the error is encoded as `<Kind>:<detail>`,
+/// i.e. `TodoNotFound:3`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TodoError {
+    TodoNotFound(u32),
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TodoError::TodoNotFound(id) => write!(f, "TodoNotFound:{}", id),
+        }
+    }
+}
+
+impl std::error::Error for TodoError {}
+
+/// Posts the reply of a message that succeeded or [Reply::Failed] with the reason it didn't.
+fn post_result(req_id: u64, result: Result<Reply, TodoError>) {
+    match result {
+        Ok(reply) => replies::post(reply),
+        Err(err) => replies::post(Reply::Failed(req_id, err.to_string())),
+    }
+}
+
+/// Replies affecting multiple todos carry their ids separated by [ID_SEPARATOR], since reply
+/// data is always a string.
+fn join_ids(ids: &[u32]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(&ID_SEPARATOR.to_string())
 }
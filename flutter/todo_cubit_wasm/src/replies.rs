@@ -76,12 +76,12 @@ impl From<Reply> for ReplyStruct {
         match reply {
             Reply::AddedTodo(req_id, data) => ReplyStruct::with_data(0, req_id, data),
             Reply::RemovedTodo(req_id, data) => ReplyStruct::with_data(1, req_id, data),
-            Reply::RemovedCompleted(req_id) => ReplyStruct::with_req_id(2, req_id),
+            Reply::RemovedCompleted(req_id, data) => ReplyStruct::with_data(2, req_id, data),
             Reply::CompletedTodo(req_id, data) => ReplyStruct::with_data(3, req_id, data),
             Reply::RestartedTodo(req_id, data) => ReplyStruct::with_data(4, req_id, data),
             Reply::ToggledTodo(req_id, data) => ReplyStruct::with_data(5, req_id, data),
-            Reply::CompletedAll(req_id) => ReplyStruct::with_req_id(6, req_id),
-            Reply::RestartedAll(req_id) => ReplyStruct::with_req_id(7, req_id),
+            Reply::CompletedAll(req_id, data) => ReplyStruct::with_data(6, req_id, data),
+            Reply::RestartedAll(req_id, data) => ReplyStruct::with_data(7, req_id, data),
             Reply::SetFilter(req_id) => ReplyStruct::with_req_id(8, req_id),
             Reply::CompletedTodoExpired => Self {
                 ty: 10,
@@ -93,6 +93,7 @@ impl From<Reply> for ReplyStruct {
                 req_id: 0,
                 data,
             },
+            Reply::Failed(req_id, data) => ReplyStruct::with_data(12, req_id, data),
         }
     }
 }